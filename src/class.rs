@@ -0,0 +1,124 @@
+use crate::error::LoxError;
+use crate::function::LoxFunction;
+use crate::interpreter::{Interpreter, Value};
+use crate::scanner;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+pub struct LoxClass {
+    pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
+    pub methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Rc<LoxFunction>>,
+    ) -> LoxClass {
+        LoxClass {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => match &self.superclass {
+                Some(superclass) => superclass.find_method(name),
+                None => None,
+            },
+        }
+    }
+
+    // Names of every method callable on an instance, this class's first.
+    pub fn method_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.methods.keys().cloned().collect();
+        names.sort();
+        if let Some(superclass) = &self.superclass {
+            names.extend(superclass.method_names());
+        }
+        names
+    }
+
+    pub fn arity(&self) -> usize {
+        match self.find_method("init") {
+            Some(initializer) => initializer.arity(),
+            None => 0,
+        }
+    }
+
+    pub fn call(
+        self: &Rc<Self>,
+        interp: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> Result<Value, LoxError> {
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance::new(self.clone()))));
+        if let Some(initializer) = self.find_method("init") {
+            initializer.bind(instance.clone()).call(interp, arguments)?;
+        }
+        Ok(instance)
+    }
+}
+
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    pub fields: HashMap<String, Value>,
+}
+
+// Fields may point back at the instance, so only the class name is shown.
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn get(
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &scanner::Token,
+    ) -> Result<Value, LoxError> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexme) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexme);
+        match method {
+            Some(method) => Ok(Value::Function(Rc::new(
+                method.bind(Value::Instance(instance.clone())),
+            ))),
+            None => {
+                let instance = instance.borrow();
+                let mut names: Vec<String> = instance.fields.keys().cloned().collect();
+                names.sort();
+                names.extend(instance.class.method_names());
+                Err(
+                    LoxError::runtime(name, format!("Undefined property '{}'", name.lexme))
+                        .suggest(&name.lexme, names.iter().map(String::as_str)),
+                )
+            }
+        }
+    }
+
+    pub fn set(&mut self, name: &scanner::Token, value: Value) {
+        self.fields.insert(name.lexme.clone(), value);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::error::{Frame, LoxError};
+use crate::lint::Warning;
+use crate::scanner::{Span, TokenType};
+use serde_json::json;
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal, Write};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+// How diagnostics are written out: for people, or one JSON object per
+// line for editors and CI.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    fn colour(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+// A message about the user's program, rendered the way rustc does: a
+// header, the file position, the offending source line with the span
+// underlined, then any notes and help.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // A stable name for the kind of problem, such as `parse-error` or
+    // `unused-variable`.
+    pub code: Option<String>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code: None,
+            message,
+            span: None,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: &str) -> Diagnostic {
+        self.code = Some(String::from(code));
+        self
+    }
+
+    pub fn with_span(mut self, span: Option<Span>) -> Diagnostic {
+        self.span = span;
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Diagnostic {
+        self.help = help;
+        self
+    }
+
+    // Writes the diagnostic to stderr. Human output is coloured only when
+    // that's a terminal.
+    pub fn emit(&self, file: &str, source: &str, format: ErrorFormat) {
+        let out = match format {
+            ErrorFormat::Human => self.render(file, source, io::stderr().is_terminal()),
+            ErrorFormat::Json => self.render_json(file, source) + "\n",
+        };
+        let _ = io::stderr().write_all(out.as_bytes());
+    }
+
+    pub fn render_json(&self, file: &str, source: &str) -> String {
+        let span = self.span.map(|span| {
+            // Lines and columns are 1-based, and the end is exclusive.
+            let before_end = source.get(..span.end).unwrap_or(source);
+            let end_line = before_end.matches('\n').count() + 1;
+            let line_start = before_end.rfind('\n').map_or(0, |newline| newline + 1);
+            let end_column = before_end[line_start..].chars().count() + 1;
+            json!({
+                "start": span.start,
+                "end": span.end,
+                "line": span.line,
+                "column": span.column,
+                "end_line": end_line,
+                "end_column": end_column,
+            })
+        });
+        json!({
+            "severity": self.severity.label(),
+            "code": self.code,
+            "message": self.message,
+            "file": file,
+            "span": span,
+            "notes": self.notes,
+            "help": self.help,
+        })
+        .to_string()
+    }
+
+    pub fn render(&self, file: &str, source: &str, colour: bool) -> String {
+        let paint = |style: &'static str| if colour { style } else { "" };
+        let reset = paint(RESET);
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "{}{}{}{}: {}{}",
+            paint(self.severity.colour()),
+            self.severity.label(),
+            reset,
+            paint(BOLD),
+            self.message,
+            reset
+        );
+
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                let _ = writeln!(out, "{}-->{} {}", paint(BLUE), reset, file);
+                self.render_footer(&mut out, "", colour);
+                return out;
+            }
+        };
+
+        let line_text = source.lines().nth(span.line - 1).unwrap_or("");
+        let gutter = " ".repeat(span.line.to_string().len());
+        let _ = writeln!(
+            out,
+            "{}{}-->{} {}:{}:{}",
+            gutter,
+            paint(BLUE),
+            reset,
+            file,
+            span.line,
+            span.column
+        );
+        let _ = writeln!(out, "{} {}|{}", gutter, paint(BLUE), reset);
+        let _ = writeln!(out, "{}{} |{} {}", paint(BLUE), span.line, reset, line_text);
+
+        // Keep tabs in the padding so the carets line up with the source.
+        let before: String = line_text
+            .chars()
+            .take(span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // Spans over several lines are only underlined to the end of the first.
+        let width = source
+            .get(span.start..span.end)
+            .map(|text| text.lines().next().unwrap_or("").chars().count())
+            .unwrap_or(0)
+            .max(1);
+        let _ = writeln!(
+            out,
+            "{} {}|{} {}{}{}{}",
+            gutter,
+            paint(BLUE),
+            reset,
+            before,
+            paint(self.severity.colour()),
+            "^".repeat(width),
+            reset
+        );
+
+        self.render_footer(&mut out, &gutter, colour);
+        out
+    }
+
+    fn render_footer(&self, out: &mut String, gutter: &str, colour: bool) {
+        let paint = |style: &'static str| if colour { style } else { "" };
+        for note in &self.notes {
+            // Later lines of a multi-line note line up under the first.
+            let indent = format!("\n{}         ", gutter);
+            let _ = writeln!(
+                out,
+                "{} {}={} {}note{}: {}",
+                gutter,
+                paint(BLUE),
+                paint(RESET),
+                paint(BOLD),
+                paint(RESET),
+                note.replace('\n', &indent)
+            );
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(
+                out,
+                "{} {}={} {}help{}: {}",
+                gutter,
+                paint(BLUE),
+                paint(RESET),
+                paint(BOLD),
+                paint(RESET),
+                help
+            );
+        }
+    }
+}
+
+impl From<&LoxError> for Diagnostic {
+    fn from(err: &LoxError) -> Diagnostic {
+        let code = format!("{}-error", err.phase.to_string().to_lowercase());
+        let mut diagnostic = Diagnostic::new(Severity::Error, err.message.clone())
+            .with_code(&code)
+            .with_span(err.span)
+            .with_help(err.help.as_deref().map(String::from));
+        if let Some(token) = &err.token {
+            if token.tok_type == TokenType::Eof {
+                diagnostic = diagnostic.with_note(String::from("reached the end of the input"));
+            }
+        }
+        if !err.trace.is_empty() {
+            diagnostic = diagnostic.with_note(format!("stack trace:\n{}", backtrace(&err.trace)));
+        }
+        diagnostic
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(Severity::Warning, warning.message.clone())
+            .with_code(warning.kind.name())
+            .with_span(Some(warning.span))
+            .with_help(warning.help.clone());
+        if let Some(note) = &warning.note {
+            diagnostic = diagnostic.with_note(note.clone());
+        }
+        diagnostic.with_note(format!(
+            "`--allow {}` silences this warning",
+            warning.kind.name()
+        ))
+    }
+}
+
+// Formats a backtrace one frame per line, folding runs of identical frames
+// from deep recursion into a single line.
+fn backtrace(trace: &[Frame]) -> String {
+    let mut lines = Vec::new();
+    let mut frames = trace.iter().peekable();
+    while let Some(frame) = frames.next() {
+        let mut repeats = 0;
+        while frames.peek() == Some(&frame) {
+            frames.next();
+            repeats += 1;
+        }
+        lines.push(frame.to_string());
+        match repeats {
+            0 => (),
+            1 => lines.push(frame.to_string()),
+            _ => lines.push(format!("[previous frame repeated {} more times]", repeats)),
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const SOURCE: &str = "var x = 1;\nprint y;\n";
+
+    // The `y` on line 2.
+    fn y_span() -> Span {
+        Span::new(0, 17, 18, 2, 7)
+    }
+
+    fn undefined_y() -> Diagnostic {
+        Diagnostic::new(Severity::Error, String::from("Undefined variable 'y'"))
+            .with_code("runtime-error")
+            .with_span(Some(y_span()))
+            .with_note(String::from("stack trace:\n[line 2] in script"))
+            .with_help(Some(String::from("did you mean `x`?")))
+    }
+
+    fn json(diagnostic: &Diagnostic, source: &str) -> Value {
+        serde_json::from_str(&diagnostic.render_json("test.lox", source)).unwrap()
+    }
+
+    #[test]
+    fn json_has_every_field() {
+        let json = json(&undefined_y(), SOURCE);
+        assert_eq!(json["severity"], "error");
+        assert_eq!(json["code"], "runtime-error");
+        assert_eq!(json["message"], "Undefined variable 'y'");
+        assert_eq!(json["file"], "test.lox");
+        assert_eq!(json["notes"][0], "stack trace:\n[line 2] in script");
+        assert_eq!(json["help"], "did you mean `x`?");
+    }
+
+    #[test]
+    fn json_span_has_byte_offsets_and_exclusive_end_column() {
+        let span = &json(&undefined_y(), SOURCE)["span"];
+        assert_eq!(span["start"], 17);
+        assert_eq!(span["end"], 18);
+        assert_eq!(span["line"], 2);
+        assert_eq!(span["column"], 7);
+        assert_eq!(span["end_line"], 2);
+        assert_eq!(span["end_column"], 8);
+    }
+
+    #[test]
+    fn json_span_can_end_on_a_later_line() {
+        let diagnostic = Diagnostic::new(Severity::Warning, String::from("w"))
+            .with_span(Some(Span::new(0, 0, 13, 1, 1)));
+        let span = &json(&diagnostic, SOURCE)["span"];
+        assert_eq!(span["end_line"], 2);
+        assert_eq!(span["end_column"], 3);
+    }
+
+    #[test]
+    fn json_columns_count_characters_not_bytes() {
+        let diagnostic = Diagnostic::new(Severity::Error, String::from("e"))
+            .with_span(Some(Span::new(0, 5, 6, 1, 5)));
+        let span = &json(&diagnostic, "é + y;")["span"];
+        assert_eq!(span["start"], 5);
+        assert_eq!(span["end_column"], 6);
+    }
+
+    #[test]
+    fn json_without_a_span_or_extras_uses_null() {
+        let diagnostic = Diagnostic::new(Severity::Error, String::from("can't read"));
+        let json = json(&diagnostic, "");
+        assert_eq!(json["span"], Value::Null);
+        assert_eq!(json["code"], Value::Null);
+        assert_eq!(json["help"], Value::Null);
+        assert_eq!(json["notes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let expected = "\
+error: Undefined variable 'y'
+ --> test.lox:2:7
+  |
+2 | print y;
+  |       ^
+  = note: stack trace:
+          [line 2] in script
+  = help: did you mean `x`?
+";
+        assert_eq!(undefined_y().render("test.lox", SOURCE, false), expected);
+    }
+
+    #[test]
+    fn render_widens_the_gutter_for_long_line_numbers() {
+        let source = format!("{}oops", "\n".repeat(9));
+        let diagnostic = Diagnostic::new(Severity::Warning, String::from("w"))
+            .with_span(Some(Span::new(0, 9, 13, 10, 1)));
+        let expected = "\
+warning: w
+  --> f.lox:10:1
+   |
+10 | oops
+   | ^^^^
+";
+        assert_eq!(diagnostic.render("f.lox", &source, false), expected);
+    }
+
+    #[test]
+    fn render_keeps_tabs_so_carets_line_up() {
+        let diagnostic = Diagnostic::new(Severity::Error, String::from("e"))
+            .with_span(Some(Span::new(0, 2, 3, 1, 3)));
+        let rendered = diagnostic.render("f.lox", "\t\tx", false);
+        assert!(rendered.ends_with("1 | \t\tx\n  | \t\t^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_underlines_only_the_first_line_of_a_multi_line_span() {
+        let diagnostic = Diagnostic::new(Severity::Error, String::from("e"))
+            .with_span(Some(Span::new(0, 0, 13, 1, 1)));
+        let rendered = diagnostic.render("f.lox", SOURCE, false);
+        assert!(rendered.contains("  | ^^^^^^^^^^\n"), "{}", rendered);
+    }
+
+    #[test]
+    fn render_without_a_span_names_only_the_file() {
+        let diagnostic =
+            Diagnostic::new(Severity::Error, String::from("can't read")).with_code("io-error");
+        assert_eq!(
+            diagnostic.render("missing.lox", "", false),
+            "error: can't read\n--> missing.lox\n"
+        );
+    }
+
+    #[test]
+    fn render_colours_only_when_asked() {
+        assert!(!undefined_y()
+            .render("f.lox", SOURCE, false)
+            .contains('\x1b'));
+        assert!(undefined_y()
+            .render("f.lox", SOURCE, true)
+            .starts_with("\x1b[1;31merror\x1b[0m"));
+    }
+}
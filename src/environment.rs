@@ -1,17 +1,20 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
+use crate::error::LoxError;
 use crate::interpreter::Value;
 use crate::scanner::Token;
-use std::io::{Error, ErrorKind};
 
 #[derive(Debug, Clone)]
 pub struct Environment {
     pub values: HashMap<String, Value>,
-    pub enclosing: Option<Box<Environment>>,
+    pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
-    pub fn new(enclosing: Option<Box<Environment>>) -> Environment {
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Environment {
         Environment {
             values: HashMap::new(),
             enclosing,
@@ -21,40 +24,116 @@ impl Environment {
     pub fn define(&mut self, name: String, value: Value) {
         self.values.insert(name, value);
     }
-    pub fn assign(&mut self, name: Token, value: Value) -> Result<(), Error> {
-        if self.values.contains_key(&name.lexme) {
-            self.values.insert(name.clone().lexme, value.clone());
-            return Ok(());
+    pub fn assign(&mut self, name: Token, value: Value) -> Result<(), LoxError> {
+        if self.try_assign(&name.lexme, value) {
+            Ok(())
+        } else {
+            Err(self.undefined(&name))
+        }
+    }
+
+    fn try_assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
         }
-        match &mut self.enclosing {
-            Some(env) => {
-                env.assign(name, value)?;
-                return Ok(());
-            }
-            None => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("Failed to assign. There is no variable assigned"),
-                ))
-            }
+        match &self.enclosing {
+            Some(env) => env.borrow_mut().try_assign(name, value),
+            None => false,
+        }
+    }
+
+    pub fn get(&self, name: Token) -> Result<Value, LoxError> {
+        match self.find(&name.lexme) {
+            Some(val) => Ok(val),
+            None => Err(self.undefined(&name)),
         }
     }
 
-    pub fn get(&self, name: Token) -> Result<Value, Error> {
-        match self.values.get(&name.lexme).clone() {
-            Some(val) => Ok(val.clone()),
+    fn find(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(val) => Some(val.clone()),
             None => match &self.enclosing {
-                Some(enclose_env) => match enclose_env.values.get(&name.lexme).clone() {
-                    Some(val) => Ok(val.clone()),
-                    None => enclose_env.get(name),
-                },
-                None => {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        format!("Undefined variable name {} ", name.lexme),
-                    ))
-                }
+                Some(enclose_env) => enclose_env.borrow().find(name),
+                None => None,
             },
         }
     }
+
+    // Every name visible from this scope, innermost scope first.
+    fn visible_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        names.sort();
+        if let Some(env) = &self.enclosing {
+            names.extend(env.borrow().visible_names());
+        }
+        names
+    }
+
+    // The error for a name that isn't defined, suggesting a similarly named
+    // variable from this scope or any enclosing one.
+    pub fn undefined(&self, name: &Token) -> LoxError {
+        let names = self.visible_names();
+        LoxError::runtime(name, format!("Undefined variable '{}'", name.lexme))
+            .suggest(&name.lexme, names.iter().map(String::as_str))
+    }
+
+    // Reads `name` from the scope exactly `distance` hops up the chain, as
+    // recorded by the resolver.
+    pub fn get_at(&self, distance: usize, name: &str) -> Result<Value, LoxError> {
+        if distance == 0 {
+            return match self.values.get(name) {
+                Some(val) => Ok(val.clone()),
+                None => Err(LoxError::runtime_message(format!(
+                    "Undefined variable '{}'",
+                    name
+                ))),
+            };
+        }
+        match &self.enclosing {
+            Some(env) => env.borrow().get_at(distance - 1, name),
+            None => Err(LoxError::runtime_message(format!(
+                "Undefined variable '{}'",
+                name
+            ))),
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: Token,
+        value: Value,
+    ) -> Result<(), LoxError> {
+        if distance == 0 {
+            self.values.insert(name.lexme, value);
+            return Ok(());
+        }
+        match &self.enclosing {
+            Some(env) => env.borrow_mut().assign_at(distance - 1, name, value),
+            None => Err(LoxError::runtime(
+                &name,
+                format!("Undefined variable '{}'", name.lexme),
+            )),
+        }
+    }
+}
+
+// Lists each scope from this one out to the globals, a name per line.
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.enclosing {
+            Some(_) => writeln!(f, "scope:")?,
+            None => writeln!(f, "globals:")?,
+        }
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+        for name in names {
+            writeln!(f, "  {} = {}", name, self.values[name])?;
+        }
+        match &self.enclosing {
+            Some(env) => write!(f, "{}", env.borrow()),
+            None => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,186 @@
+use crate::scanner::{Span, Token, TokenType};
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Phase {
+    Scan,
+    Parse,
+    Resolve,
+    Runtime,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phase::Scan => write!(f, "Scan"),
+            Phase::Parse => write!(f, "Parse"),
+            Phase::Resolve => write!(f, "Resolve"),
+            Phase::Runtime => write!(f, "Runtime"),
+        }
+    }
+}
+
+// A function that was running when a runtime error was raised, and the
+// line it had reached. `None` is the top-level script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: Option<String>,
+    pub line: usize,
+}
+
+impl Frame {
+    pub fn new(name: Option<String>, line: usize) -> Frame {
+        Frame { name, line }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "[line {}] in {}()", self.line, name),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
+}
+
+// Every failure the interpreter can report, from a bad character in the
+// source to a type error while running.
+#[derive(Debug, Clone)]
+pub struct LoxError {
+    pub phase: Phase,
+    pub message: String,
+    // Boxed to keep `Result<_, LoxError>` small on the happy path.
+    pub token: Option<Box<Token>>,
+    pub span: Option<Span>,
+    // Boxed for the same reason; it's never grown once set.
+    pub help: Option<Box<str>>,
+    // Innermost call first; empty unless raised inside a Lox function.
+    pub trace: Box<[Frame]>,
+    // Set when the source ended inside a string, so more input could still
+    // complete it. The REPL keeps reading lines while this is set.
+    pub unterminated: bool,
+}
+
+impl LoxError {
+    pub fn new(phase: Phase, message: String, token: Option<Token>) -> LoxError {
+        let span = token.as_ref().map(|token| token.span());
+        LoxError {
+            phase,
+            message,
+            token: token.map(Box::new),
+            span,
+            help: None,
+            trace: Box::new([]),
+            unterminated: false,
+        }
+    }
+
+    pub fn scan(span: Span, message: String) -> LoxError {
+        let mut err = LoxError::new(Phase::Scan, message, None);
+        err.span = Some(span);
+        err
+    }
+
+    pub fn parse(token: &Token, message: String) -> LoxError {
+        LoxError::new(Phase::Parse, message, Some(token.clone()))
+    }
+
+    pub fn resolve(token: &Token, message: String) -> LoxError {
+        LoxError::new(Phase::Resolve, message, Some(token.clone()))
+    }
+
+    pub fn runtime(token: &Token, message: String) -> LoxError {
+        LoxError::new(Phase::Runtime, message, Some(token.clone()))
+    }
+
+    // A runtime error raised where no token is at hand, such as inside a
+    // native function. The caller attaches a location with `or_at`.
+    pub fn runtime_message(message: String) -> LoxError {
+        LoxError::new(Phase::Runtime, message, None)
+    }
+
+    pub fn unterminated(mut self) -> LoxError {
+        self.unterminated = true;
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> LoxError {
+        self.help = Some(help.into());
+        self
+    }
+
+    // The line the error points at, or 0 when it has no location.
+    pub fn line(&self) -> usize {
+        self.span.map_or(0, |span| span.line)
+    }
+
+    // Adds "did you mean" help when one of `candidates` looks like a typo of
+    // `name`. Candidates earlier in the list win ties.
+    pub fn suggest<'a, I>(mut self, name: &str, candidates: I) -> LoxError
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let max_distance = (name.chars().count() / 3).max(1);
+        let mut best: Option<(usize, &str)> = None;
+        for candidate in candidates {
+            let distance = strsim::osa_distance(name, candidate);
+            if candidate == name || distance > max_distance {
+                continue;
+            }
+            if best.is_none_or(|(best_distance, _)| distance < best_distance) {
+                best = Some((distance, candidate));
+            }
+        }
+        if let Some((_, candidate)) = best {
+            self.help = Some(format!("did you mean `{}`?", candidate).into());
+        }
+        self
+    }
+
+    pub fn or_at(mut self, token: &Token) -> LoxError {
+        if self.token.is_none() {
+            self.token = Some(Box::new(token.clone()));
+            self.span = Some(token.span());
+        }
+        self
+    }
+
+    // Like `or_at`, for when only the enclosing node's span is known.
+    pub fn or_within(mut self, span: Span) -> LoxError {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.token {
+            Some(token) if token.tok_type == TokenType::Eof => write!(
+                f,
+                "[line {}] {} error at end: {}",
+                self.line(),
+                self.phase,
+                self.message
+            ),
+            Some(token) => write!(
+                f,
+                "[line {}] {} error at '{}': {}",
+                self.line(),
+                self.phase,
+                token.lexme,
+                self.message
+            ),
+            None => write!(
+                f,
+                "[line {}] {} error: {}",
+                self.line(),
+                self.phase,
+                self.message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {}
@@ -1,13 +1,39 @@
 use crate::scanner;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: scanner::Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: scanner::Span) -> Expr {
+        Expr { kind, span }
+    }
+}
+
+// Variable-like nodes carry the scope distance filled in by the resolver;
+// `None` means the name lives in the globals.
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub enum ExprKind {
     Literal(Literal),
     Unary(scanner::Token, Box<Expr>),
     Binary(Box<Expr>, scanner::Token, Box<Expr>),
     Grouping(Box<Expr>),
-    Identifier(scanner::Token),
-    Assign(scanner::Token, Box<Expr>),
+    Identifier(scanner::Token, Option<usize>),
+    Assign(scanner::Token, Box<Expr>, Option<usize>),
     Logical(Box<Expr>, scanner::Token, Box<Expr>),
+    Call(Box<Expr>, scanner::Token, Vec<Expr>),
+    Get(Box<Expr>, scanner::Token),
+    Set(Box<Expr>, scanner::Token, Box<Expr>),
+    This(scanner::Token, Option<usize>),
+    Super(scanner::Token, scanner::Token, Option<usize>),
+    List(Vec<Expr>),
+    Map(scanner::Token, Vec<(Expr, Expr)>),
+    Index(Box<Expr>, scanner::Token, Box<Expr>),
+    IndexSet(Box<Expr>, scanner::Token, Box<Expr>, Box<Expr>),
 }
 
 #[derive(Debug, Clone)]
@@ -18,12 +44,153 @@ pub enum Literal {
     False,
     Nil,
 }
+
 #[derive(Debug, Clone)]
-pub enum Stmt {
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: scanner::Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: scanner::Span) -> Stmt {
+        Stmt { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
     Print(Expr),
     Expr(Expr),
-    Var(String, Expr),
+    Var(scanner::Token, Expr),
     Block(Vec<Stmt>),
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
+    // The optional expression is a desugared `for` increment, kept apart from
+    // the body so that `continue` still runs it. The flag is set for every
+    // loop desugared from a `for`, so warnings can name the right keyword.
+    While(Expr, Box<Stmt>, Option<Expr>, bool),
+    Break(scanner::Token),
+    Continue(scanner::Token),
+    Function(Function),
+    Return(scanner::Token, Option<Expr>),
+    Class(scanner::Token, Option<Expr>, Vec<Function>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: scanner::Token,
+    pub params: Vec<scanner::Token>,
+    // Shared with every closure made from the declaration, so creating or
+    // binding one doesn't copy the tree.
+    pub body: Rc<[Stmt]>,
+}
+
+// Prints the tree as S-expressions, e.g. `(+ 1 (* 2 3))`, for the REPL's
+// `:ast` command.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Literal(Literal::Number(num)) => write!(f, "{}", num),
+            ExprKind::Literal(Literal::String(string)) => write!(f, "{:?}", string),
+            ExprKind::Literal(Literal::True) => write!(f, "true"),
+            ExprKind::Literal(Literal::False) => write!(f, "false"),
+            ExprKind::Literal(Literal::Nil) => write!(f, "nil"),
+            ExprKind::Unary(op, right) => write!(f, "({} {})", op.lexme, right),
+            ExprKind::Binary(left, op, right) | ExprKind::Logical(left, op, right) => {
+                write!(f, "({} {} {})", op.lexme, left, right)
+            }
+            ExprKind::Grouping(e) => write!(f, "(group {})", e),
+            ExprKind::Identifier(name, _) => write!(f, "{}", name.lexme),
+            ExprKind::Assign(name, value, _) => write!(f, "(= {} {})", name.lexme, value),
+            ExprKind::Call(callee, _, args) => {
+                write!(f, "(call {}", callee)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Get(object, name) => write!(f, "(. {} {})", object, name.lexme),
+            ExprKind::Set(object, name, value) => {
+                write!(f, "(= (. {} {}) {})", object, name.lexme, value)
+            }
+            ExprKind::This(..) => write!(f, "this"),
+            ExprKind::Super(_, method, _) => write!(f, "(super {})", method.lexme),
+            ExprKind::List(elements) => {
+                write!(f, "(list")?;
+                for element in elements {
+                    write!(f, " {}", element)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Map(_, entries) => {
+                write!(f, "(map")?;
+                for (key, value) in entries {
+                    write!(f, " ({} {})", key, value)?;
+                }
+                write!(f, ")")
+            }
+            ExprKind::Index(object, _, index) => write!(f, "(index {} {})", object, index),
+            ExprKind::IndexSet(object, _, index, value) => {
+                write!(f, "(= (index {} {}) {})", object, index, value)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            StmtKind::Print(e) => write!(f, "(print {})", e),
+            StmtKind::Expr(e) => write!(f, "{}", e),
+            StmtKind::Var(name, initializer) => write!(f, "(var {} {})", name.lexme, initializer),
+            StmtKind::Block(statements) => {
+                write!(f, "(block")?;
+                for stmt in statements {
+                    write!(f, " {}", stmt)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                write!(f, "(if {} {}", condition, then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {}", else_branch)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::While(condition, body, increment, _) => {
+                write!(f, "(while {} {}", condition, body)?;
+                if let Some(increment) = increment {
+                    write!(f, " {}", increment)?;
+                }
+                write!(f, ")")
+            }
+            StmtKind::Break(_) => write!(f, "(break)"),
+            StmtKind::Continue(_) => write!(f, "(continue)"),
+            StmtKind::Function(function) => write!(f, "{}", function),
+            StmtKind::Return(_, value) => match value {
+                Some(value) => write!(f, "(return {})", value),
+                None => write!(f, "(return)"),
+            },
+            StmtKind::Class(name, superclass, methods) => {
+                write!(f, "(class {}", name.lexme)?;
+                if let Some(superclass) = superclass {
+                    write!(f, " (< {})", superclass)?;
+                }
+                for method in methods {
+                    write!(f, " {}", method)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<&str> = self.params.iter().map(|p| p.lexme.as_str()).collect();
+        write!(f, "(fun {} ({})", self.name.lexme, params.join(" "))?;
+        for stmt in self.body.iter() {
+            write!(f, " {}", stmt)?;
+        }
+        write!(f, ")")
+    }
 }
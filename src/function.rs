@@ -0,0 +1,99 @@
+use crate::environment::Environment;
+use crate::error::LoxError;
+use crate::expr;
+use crate::interpreter::{Interpreter, Unwind, Value};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+pub type NativeFn = Rc<dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, LoxError>>;
+
+// A builtin implemented in Rust and exposed to scripts as a global.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub func: NativeFn,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl NativeFunction {
+    pub fn new(name: String, arity: usize, func: NativeFn) -> NativeFunction {
+        NativeFunction { name, arity, func }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoxFunction {
+    pub declaration: expr::Function,
+    pub closure: Rc<RefCell<Environment>>,
+    pub is_initializer: bool,
+}
+
+// The closure can hold the function itself, so Debug must not walk into it.
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexme)
+    }
+}
+
+impl LoxFunction {
+    pub fn new(
+        declaration: expr::Function,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> LoxFunction {
+        LoxFunction {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexme
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    // Returns a copy of the method whose closure has `this` bound to the instance.
+    pub fn bind(&self, instance: Value) -> LoxFunction {
+        let mut env = Environment::new(Some(self.closure.clone()));
+        env.define(String::from("this"), instance);
+        LoxFunction::new(
+            self.declaration.clone(),
+            Rc::new(RefCell::new(env)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Value {
+        match self.closure.borrow().values.get("this") {
+            Some(instance) => instance.clone(),
+            None => Value::Nil,
+        }
+    }
+
+    pub fn call(&self, interp: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, LoxError> {
+        let mut env = Environment::new(Some(self.closure.clone()));
+        for (param, arg) in self.declaration.params.iter().zip(arguments) {
+            env.define(param.lexme.clone(), arg);
+        }
+
+        let result = interp.execute_block(&self.declaration.body, Rc::new(RefCell::new(env)));
+        match result {
+            Ok(()) | Err(Unwind::Return(_)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(err)) => Err(err),
+            Err(unwind) => Err(LoxError::runtime_message(unwind.to_string())),
+        }
+    }
+}
@@ -1,154 +1,407 @@
+use crate::class::{LoxClass, LoxInstance};
 use crate::environment::Environment;
+use crate::error::{Frame, LoxError};
 use crate::expr;
+use crate::function::{LoxFunction, NativeFunction};
 use crate::scanner;
-use log::info;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+// The largest `--max-depth` accepted; past this the stack guard below is
+// what stops recursion anyway.
+pub const MAX_DEPTH_LIMIT: usize = 100_000;
+// The stack of a thread spawned with Rust's defaults.
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+// Stack left unused by Lox code, for unwinding an error and running natives.
+const STACK_RESERVE: usize = 256 * 1024;
+
 pub struct Interpreter {
-    pub env: Environment,
+    pub env: Rc<RefCell<Environment>>,
+    pub globals: Rc<RefCell<Environment>>,
+    // One entry per active Lox call: the callee's name and the line it was
+    // called from.
+    frames: Vec<Frame>,
+    max_depth: usize,
+    // Where the Rust stack was when the interpreter was made, and how far
+    // past that evaluation may go before "Stack overflow" is raised.
+    stack_start: usize,
+    stack_budget: usize,
 }
-use std::io::{Error, ErrorKind};
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
     String(String),
     Bool(bool),
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<IndexMap<Value, Value>>>),
     Nil,
 }
 
+impl Value {
+    // The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Nil => "nil",
+        }
+    }
+
+    // `seen` holds the lists and maps currently being written, so one that
+    // contains itself prints as `[...]` or `{...}` instead of recursing forever.
+    fn write(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::String(string) => write!(f, "{}", string),
+            Value::Number(num) if num.is_nan() => write!(f, "nan"),
+            Value::Number(num) if num.is_infinite() => {
+                write!(f, "{}", if *num > 0.0 { "inf" } else { "-inf" })
+            }
+            // Integral numbers print without a trailing `.0`.
+            Value::Number(num) => write!(f, "{}", num),
+            Value::Bool(boolean) => write!(f, "{}", boolean),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::Native(native) => write!(f, "<native fn {}>", native.name),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class.name),
+            Value::List(list) => {
+                let ptr = Rc::as_ptr(list) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "[...]");
+                }
+                seen.push(ptr);
+                write!(f, "[")?;
+                for (i, element) in list.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write_element(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "]")
+            }
+            Value::Map(map) => {
+                let ptr = Rc::as_ptr(map) as *const ();
+                if seen.contains(&ptr) {
+                    return write!(f, "{{...}}");
+                }
+                seen.push(ptr);
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.write_element(f, seen)?;
+                    write!(f, ": ")?;
+                    value.write_element(f, seen)?;
+                }
+                seen.pop();
+                write!(f, "}}")
+            }
+            Value::Nil => write!(f, "nil"),
+        }
+    }
+
+    // Strings inside a list or map are quoted and escaped, so `["a, b"]`
+    // can't be mistaken for two elements.
+    fn write_element(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::String(string) => write!(f, "{:?}", string),
+            _ => self.write(f, seen),
+        }
+    }
+
+    // The value as it's written inside a list or map, with strings quoted.
+    pub fn to_element_string(&self) -> String {
+        struct Element<'a>(&'a Value);
+        impl fmt::Display for Element<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.write_element(f, &mut Vec::new())
+            }
+        }
+        Element(self).to_string()
+    }
+
+    // Only immutable values can be used as map keys. NaN is left out since
+    // it isn't equal to itself, so it could never be found again.
+    pub fn check_map_key(&self) -> Result<(), &'static str> {
+        match self {
+            Value::Number(num) if num.is_nan() => Err("NaN can't be used as a map key"),
+            Value::Number(_) | Value::String(_) | Value::Bool(_) | Value::Nil => Ok(()),
+            _ => Err("Map keys must be strings, numbers, booleans or nil"),
+        }
+    }
+}
+
+// Primitives compare by value, everything else by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// Not reflexive for NaN, but NaN is never a map key (see `check_map_key`).
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            // 0 and -0 are equal, so they must hash the same.
+            Value::Number(num) if *num == 0.0 => 0.0f64.to_bits().hash(state),
+            Value::Number(num) => num.to_bits().hash(state),
+            Value::String(string) => string.hash(state),
+            Value::Bool(boolean) => boolean.hash(state),
+            Value::Nil => (),
+            Value::Function(function) => Rc::as_ptr(function).hash(state),
+            Value::Native(native) => Rc::as_ptr(native).hash(state),
+            Value::Class(class) => Rc::as_ptr(class).hash(state),
+            Value::Instance(instance) => Rc::as_ptr(instance).hash(state),
+            Value::List(list) => Rc::as_ptr(list).hash(state),
+            Value::Map(map) => Rc::as_ptr(map).hash(state),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+// Anything that stops a statement from running to completion.
+#[derive(Debug)]
+pub enum Unwind {
+    Error(LoxError),
+    Return(Value),
+    Break,
+    Continue,
+}
+
+impl From<LoxError> for Unwind {
+    fn from(err: LoxError) -> Unwind {
+        Unwind::Error(err)
+    }
+}
+
+impl fmt::Display for Unwind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unwind::Error(err) => write!(f, "{}", err),
+            Unwind::Return(_) => write!(f, "Can't return from top-level code"),
+            Unwind::Break => write!(f, "Can't use 'break' outside of a loop"),
+            Unwind::Continue => write!(f, "Can't use 'continue' outside of a loop"),
+        }
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Interpreter {
+        let globals = Rc::new(RefCell::new(Environment::new(None)));
         Interpreter {
-            env: Environment::new(None),
+            env: globals.clone(),
+            globals,
+            frames: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack_start: stack_position(),
+            stack_budget: DEFAULT_STACK_SIZE - STACK_RESERVE,
+        }
+    }
+
+    // Makes a function implemented in Rust a global. The builtins and any an
+    // embedder adds are registered on `Lox`, which defines them here.
+    pub fn define_native(&mut self, native: Rc<NativeFunction>) {
+        self.globals
+            .borrow_mut()
+            .define(native.name.clone(), Value::Native(native));
+    }
+
+    // Limits how deeply Lox calls may nest before "Stack overflow" is raised.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // Tells the interpreter how big the stack of the thread running it is.
+    // Nesting is limited by what's actually left of it, since how much a
+    // Lox call or block uses depends on the code and the build.
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_budget = stack_size.saturating_sub(STACK_RESERVE);
+    }
+
+    fn stack_exhausted(&self) -> bool {
+        stack_position().abs_diff(self.stack_start) > self.stack_budget
+    }
+
+    // Builds the backtrace for an error raised in the innermost frame, from
+    // the failing line out to the top-level script.
+    fn backtrace(&self, line: usize) -> Vec<Frame> {
+        let mut trace = Vec::new();
+        let mut line = line;
+        for frame in self.frames.iter().rev() {
+            trace.push(Frame::new(frame.name.clone(), line));
+            line = frame.line;
         }
+        trace.push(Frame::new(None, line));
+        trace
     }
 
-    pub fn evaluate(&mut self, expr: expr::Expr) -> Result<Value, Error> {
-        match expr {
-            expr::Expr::Literal(lit) => Ok(self.visit_literal_expr(lit)),
-            expr::Expr::Grouping(e) => self.evaluate(*e),
-            expr::Expr::Unary(op, e) => self.visit_unary_expr(op.clone(), *e),
-            expr::Expr::Binary(left, op, right) => self.visit_binary_expr(*left, op, *right),
-            expr::Expr::Identifier(tok) => self.visit_identifier_expr(tok),
-            expr::Expr::Assign(tok, e) => self.visit_assign_expr(tok, *e),
-            expr::Expr::Logical(left, op, right) => self.visit_logical_expr(*left, op, *right),
+    pub fn evaluate(&mut self, expr: &expr::Expr) -> Result<Value, LoxError> {
+        if self.stack_exhausted() {
+            return Err(
+                LoxError::runtime_message(String::from("Stack overflow")).or_within(expr.span)
+            );
+        }
+        match &expr.kind {
+            expr::ExprKind::Literal(lit) => Ok(self.visit_literal_expr(lit)),
+            expr::ExprKind::Grouping(e) => self.evaluate(e),
+            expr::ExprKind::Unary(op, e) => self.visit_unary_expr(op, e),
+            expr::ExprKind::Binary(left, op, right) => self.visit_binary_expr(left, op, right),
+            expr::ExprKind::Identifier(tok, depth) => self.visit_identifier_expr(tok, *depth),
+            expr::ExprKind::Assign(tok, e, depth) => self.visit_assign_expr(tok, e, *depth),
+            expr::ExprKind::Logical(left, op, right) => self.visit_logical_expr(left, op, right),
+            expr::ExprKind::Call(callee, paren, args) => self.visit_call_expr(callee, paren, args),
+            expr::ExprKind::Get(object, name) => self.visit_get_expr(object, name),
+            expr::ExprKind::Set(object, name, value) => self.visit_set_expr(object, name, value),
+            expr::ExprKind::This(keyword, depth) => self.visit_this_expr(keyword, *depth),
+            expr::ExprKind::Super(keyword, method, depth) => {
+                self.visit_super_expr(keyword, method, *depth)
+            }
+            expr::ExprKind::List(elements) => self.visit_list_expr(elements),
+            expr::ExprKind::Map(brace, entries) => self.visit_map_expr(brace, entries),
+            expr::ExprKind::Index(object, bracket, index) => {
+                self.visit_index_expr(object, bracket, index)
+            }
+            expr::ExprKind::IndexSet(object, bracket, index, value) => {
+                self.visit_index_set_expr(object, bracket, index, value)
+            }
         }
     }
 
     fn visit_binary_expr(
         &mut self,
-        left: expr::Expr,
-        op: scanner::Token,
-        right: expr::Expr,
-    ) -> Result<Value, Error> {
-        match self.evaluate(left) {
-            Ok(val) => match val {
-                // Checking Number op Number
-                Value::Number(left_val) => match self.evaluate(right) {
-                    Ok(val) => match val {
-                        Value::Number(right_val) => match op.tok_type {
-                            scanner::TokenType::Plus => Ok(Value::Number(left_val + right_val)),
-                            scanner::TokenType::Minus => Ok(Value::Number(left_val - right_val)),
-                            scanner::TokenType::Slash => Ok(Value::Number(left_val / right_val)),
-                            scanner::TokenType::Star => Ok(Value::Number(left_val * right_val)),
-                            scanner::TokenType::Modulo => Ok(Value::Number(left_val % right_val)),
-                            // Comparison Operator
-                            scanner::TokenType::Greater => Ok(Value::Bool(left_val > right_val)),
-                            scanner::TokenType::GreaterEqual => {
-                                Ok(Value::Bool(left_val >= right_val))
-                            }
-                            scanner::TokenType::Less => Ok(Value::Bool(left_val < right_val)),
-                            scanner::TokenType::LessEqual => Ok(Value::Bool(left_val <= right_val)),
-                            scanner::TokenType::BangEqual => Ok(Value::Bool(left_val != right_val)),
-                            scanner::TokenType::EqualEqual => {
-                                Ok(Value::Bool(left_val == right_val))
-                            }
-                            _ => {
-                                return Err(Error::new(
-                                    ErrorKind::Other,
-                                    format!("Unsuppored binary expr"),
-                                ))
-                            }
-                        },
-                        _ => {
-                            return Err(Error::new(
-                                ErrorKind::Other,
-                                format!("Binary expr needs f64"),
-                            ))
-                        }
-                    },
-                    Err(msg) => return Err(msg),
-                },
-
-                // Checking String + String
-                Value::String(left_val) => match self.evaluate(right) {
-                    Ok(val) => match val {
-                        Value::String(right_val) => match op.tok_type {
-                            scanner::TokenType::Plus => {
-                                Ok(Value::String(format!("{}{}", left_val, right_val)))
-                            }
-                            _ => {
-                                return Err(Error::new(
-                                    ErrorKind::Other,
-                                    format!("Unsuppored binary expr"),
-                                ))
-                            }
-                        },
-                        _ => {
-                            return Err(Error::new(
-                                ErrorKind::Other,
-                                format!("Binary expr needs String"),
-                            ))
-                        }
-                    },
-                    Err(msg) => return Err(msg),
-                },
-
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        format!("Binary expr needs f64"),
-                    ))
+        left: &expr::Expr,
+        op: &scanner::Token,
+        right: &expr::Expr,
+    ) -> Result<Value, LoxError> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        // Any two values can be compared for equality; mixed types are
+        // never equal.
+        match op.tok_type {
+            scanner::TokenType::EqualEqual => return Ok(Value::Bool(left == right)),
+            scanner::TokenType::BangEqual => return Ok(Value::Bool(left != right)),
+            _ => (),
+        }
+
+        match (&left, &right) {
+            (Value::Number(left_val), Value::Number(right_val)) => {
+                let (left_val, right_val) = (*left_val, *right_val);
+                match op.tok_type {
+                    scanner::TokenType::Plus => Ok(Value::Number(left_val + right_val)),
+                    scanner::TokenType::Minus => Ok(Value::Number(left_val - right_val)),
+                    scanner::TokenType::Slash => Ok(Value::Number(left_val / right_val)),
+                    scanner::TokenType::Star => Ok(Value::Number(left_val * right_val)),
+                    scanner::TokenType::Modulo => Ok(Value::Number(left_val % right_val)),
+                    scanner::TokenType::Greater => Ok(Value::Bool(left_val > right_val)),
+                    scanner::TokenType::GreaterEqual => Ok(Value::Bool(left_val >= right_val)),
+                    scanner::TokenType::Less => Ok(Value::Bool(left_val < right_val)),
+                    scanner::TokenType::LessEqual => Ok(Value::Bool(left_val <= right_val)),
+                    _ => Err(LoxError::runtime(
+                        op,
+                        String::from("Unsuppored binary expr"),
+                    )),
                 }
+            }
+
+            // Strings concatenate and order lexicographically.
+            (Value::String(left_val), Value::String(right_val)) => match op.tok_type {
+                scanner::TokenType::Plus => Ok(Value::String(format!("{}{}", left_val, right_val))),
+                scanner::TokenType::Greater => Ok(Value::Bool(left_val > right_val)),
+                scanner::TokenType::GreaterEqual => Ok(Value::Bool(left_val >= right_val)),
+                scanner::TokenType::Less => Ok(Value::Bool(left_val < right_val)),
+                scanner::TokenType::LessEqual => Ok(Value::Bool(left_val <= right_val)),
+                _ => Err(binary_type_error(op, &left, &right)),
             },
-            Err(msg) => return Err(msg),
+
+            _ => Err(binary_type_error(op, &left, &right)),
         }
     }
 
-    pub fn visit_identifier_expr(&self, op: scanner::Token) -> Result<Value, Error> {
-        info!("\n\tenv: {:?}", self.env);
-        self.env.get(op)
+    pub fn visit_identifier_expr(
+        &self,
+        op: &scanner::Token,
+        depth: Option<usize>,
+    ) -> Result<Value, LoxError> {
+        self.lookup_variable(op, depth)
+    }
+
+    fn lookup_variable(
+        &self,
+        name: &scanner::Token,
+        depth: Option<usize>,
+    ) -> Result<Value, LoxError> {
+        match depth {
+            Some(distance) => self
+                .env
+                .borrow()
+                .get_at(distance, &name.lexme)
+                .map_err(|err| err.or_at(name)),
+            // The resolver found no local, but the typo may have been of one.
+            None => {
+                let value = self.globals.borrow().get(name.clone());
+                value.map_err(|_| self.env.borrow().undefined(name))
+            }
+        }
     }
 
     fn is_truthy(&self, val: Value) -> bool {
         match val {
-            Value::Bool(boolean) => {
-                if boolean {
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            Value::Nil => return false,
-            _ => return true,
+            Value::Bool(boolean) => boolean,
+            Value::Nil => false,
+            _ => true,
         }
     }
     pub fn visit_if_stmt(
         &mut self,
-        condition: expr::Expr,
-        then_branch: expr::Stmt,
-        else_branch: Option<Box<expr::Stmt>>,
-    ) -> Result<(), Error> {
-        let cond_val = match self.evaluate(condition) {
-            Ok(val) => val,
-            Err(msg) => return Err(Error::new(ErrorKind::Other, msg)),
-        };
+        condition: &expr::Expr,
+        then_branch: &expr::Stmt,
+        else_branch: &Option<Box<expr::Stmt>>,
+    ) -> Result<(), Unwind> {
+        let cond_val = self.evaluate(condition)?;
 
         if self.is_truthy(cond_val) {
             self.execute(then_branch)?;
         } else {
             match else_branch {
                 Some(else_stmt) => {
-                    self.execute(*else_stmt)?;
+                    self.execute(else_stmt)?;
                     return Ok(());
                 }
                 None => return Ok(()),
@@ -157,99 +410,433 @@ impl Interpreter {
         Ok(())
     }
 
-    pub fn visit_unary_expr(&mut self, op: scanner::Token, e: expr::Expr) -> Result<Value, Error> {
-        match self.evaluate(e) {
-            Ok(val) => match val {
-                Value::Number(num) => match op.tok_type {
-                    scanner::TokenType::Minus => Ok(Value::Number(-num)),
-                    scanner::TokenType::Plus => Ok(Value::Number(num)),
-                    scanner::TokenType::Bang => Ok(Value::Bool(false)),
-                    _ => Err(Error::new(
-                        ErrorKind::Other,
-                        format!("Op type is not minus or plus"),
-                    )),
-                },
-                _ => match op.tok_type {
-                    scanner::TokenType::Bang => Ok(Value::Bool(false)),
-                    _ => Err(Error::new(
-                        ErrorKind::Other,
-                        format!("Right is not a number"),
-                    )),
-                },
+    pub fn visit_unary_expr(
+        &mut self,
+        op: &scanner::Token,
+        e: &expr::Expr,
+    ) -> Result<Value, LoxError> {
+        let val = self.evaluate(e)?;
+        match op.tok_type {
+            scanner::TokenType::Interpolate => Ok(Value::String(val.to_string())),
+            scanner::TokenType::Bang => Ok(Value::Bool(!self.is_truthy(val))),
+            scanner::TokenType::Minus => match val {
+                Value::Number(num) => Ok(Value::Number(-num)),
+                _ => Err(LoxError::runtime(
+                    op,
+                    format!(
+                        "Operand of '{}' must be a number, got {}",
+                        op.lexme,
+                        val.type_name()
+                    ),
+                )),
             },
-            Err(err) => return Err(err),
+            _ => Err(LoxError::runtime(
+                op,
+                String::from("Unsupported unary expr"),
+            )),
         }
     }
-    pub fn visit_literal_expr(&self, lit: expr::Literal) -> Value {
+    pub fn visit_literal_expr(&self, lit: &expr::Literal) -> Value {
         match lit {
-            expr::Literal::Number(num) => Value::Number(num),
-            expr::Literal::String(str_val) => Value::String(str_val),
+            expr::Literal::Number(num) => Value::Number(*num),
+            expr::Literal::String(str_val) => Value::String(str_val.clone()),
             expr::Literal::True => Value::Bool(true),
             expr::Literal::False => Value::Bool(false),
             _ => Value::Nil,
         }
     }
 
-    fn visit_print_stmt(&mut self, expr: expr::Expr) -> Result<(), Error> {
-        let value = self.evaluate(expr);
-        match value {
-            Ok(val) => match val {
-                Value::String(string) => println!("{}", string),
-                Value::Number(num) => println!("{}", num),
-                Value::Bool(boolean) => println!("{}", boolean),
-                _ => {
-                    return Err(Error::new(
-                        ErrorKind::Other,
-                        format!("print visitor needs string not other value. recv: {val:?}"),
-                    ))
-                }
-            },
-            Err(msg) => return Err(Error::new(ErrorKind::Other, msg)),
+    fn visit_print_stmt(&mut self, expr: &expr::Expr) -> Result<(), LoxError> {
+        let value = self.evaluate(expr)?;
+        println!("{}", value);
+        Ok(())
+    }
+
+    fn visit_var_stmt(&mut self, name: &scanner::Token, expr: &expr::Expr) -> Result<(), LoxError> {
+        let value = self.evaluate(expr)?;
+
+        self.env.borrow_mut().define(name.lexme.clone(), value);
+        Ok(())
+    }
+    fn visit_assign_expr(
+        &mut self,
+        tok: &scanner::Token,
+        e: &expr::Expr,
+        depth: Option<usize>,
+    ) -> Result<Value, LoxError> {
+        let value = self.evaluate(e)?;
+        match depth {
+            Some(distance) => {
+                self.env
+                    .borrow_mut()
+                    .assign_at(distance, tok.clone(), value.clone())?
+            }
+            None => {
+                let result = self.globals.borrow_mut().assign(tok.clone(), value.clone());
+                result.map_err(|_| self.env.borrow().undefined(tok))?
+            }
         }
+        Ok(value)
+    }
+    fn visit_block_stmt(&mut self, statements: &[expr::Stmt]) -> Result<(), Unwind> {
+        let env = Environment::new(Some(self.env.clone()));
+        self.execute_block(statements, Rc::new(RefCell::new(env)))
+    }
+
+    pub fn execute_block(
+        &mut self,
+        statements: &[expr::Stmt],
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<(), Unwind> {
+        let previous = std::mem::replace(&mut self.env, env);
+        let mut result = Ok(());
+        for stmt in statements {
+            result = self.execute(stmt);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.env = previous;
+        result
+    }
+
+    fn visit_function_stmt(&mut self, declaration: &expr::Function) -> Result<(), Unwind> {
+        let name = declaration.name.lexme.clone();
+        let function = LoxFunction::new(declaration.clone(), self.env.clone(), false);
+        self.env
+            .borrow_mut()
+            .define(name, Value::Function(Rc::new(function)));
         Ok(())
     }
-    fn visit_var_stmt(&mut self, name: String, expr: expr::Expr) -> Result<(), Error> {
-        let value = match self.evaluate(expr) {
-            Ok(val) => val,
-            Err(msg) => return Err(Error::new(ErrorKind::Other, msg)),
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &scanner::Token,
+        superclass: &Option<expr::Expr>,
+        declarations: &[expr::Function],
+    ) -> Result<(), Unwind> {
+        let superclass = match superclass {
+            Some(expr) => {
+                let span = expr.span;
+                match self.evaluate(expr)? {
+                    Value::Class(class) => Some(class),
+                    _ => {
+                        return Err(LoxError::runtime_message(String::from(
+                            "Superclass must be a class",
+                        ))
+                        .or_within(span)
+                        .into())
+                    }
+                }
+            }
+            None => None,
         };
 
-        self.env.define(name, value);
+        // Methods of a subclass close over an extra scope that holds `super`.
+        let mut method_env = self.env.clone();
+        if let Some(superclass) = &superclass {
+            let mut env = Environment::new(Some(self.env.clone()));
+            env.define(String::from("super"), Value::Class(superclass.clone()));
+            method_env = Rc::new(RefCell::new(env));
+        }
+
+        let mut methods = HashMap::new();
+        for declaration in declarations {
+            let method_name = declaration.name.lexme.clone();
+            let is_initializer = method_name == "init";
+            let method = LoxFunction::new(declaration.clone(), method_env.clone(), is_initializer);
+            methods.insert(method_name, Rc::new(method));
+        }
+
+        let class = LoxClass::new(name.lexme.clone(), superclass, methods);
+        self.env
+            .borrow_mut()
+            .define(name.lexme.clone(), Value::Class(Rc::new(class)));
         Ok(())
     }
-    fn visit_assign_expr(&mut self, tok: scanner::Token, e: expr::Expr) -> Result<Value, Error> {
-        let value = match self.evaluate(e) {
-            Ok(val) => val,
-            Err(msg) => return Err(msg),
+
+    fn visit_get_expr(
+        &mut self,
+        object: &expr::Expr,
+        name: &scanner::Token,
+    ) -> Result<Value, LoxError> {
+        match self.evaluate(object)? {
+            Value::Instance(instance) => LoxInstance::get(&instance, name),
+            _ => Err(LoxError::runtime(
+                name,
+                String::from("Only instances have properties"),
+            )),
+        }
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        object: &expr::Expr,
+        name: &scanner::Token,
+        value: &expr::Expr,
+    ) -> Result<Value, LoxError> {
+        match self.evaluate(object)? {
+            Value::Instance(instance) => {
+                let value = self.evaluate(value)?;
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            _ => Err(LoxError::runtime(
+                name,
+                String::from("Only instances have fields"),
+            )),
+        }
+    }
+
+    fn visit_list_expr(&mut self, elements: &[expr::Expr]) -> Result<Value, LoxError> {
+        let mut values = Vec::new();
+        for element in elements {
+            values.push(self.evaluate(element)?);
+        }
+        Ok(Value::List(Rc::new(RefCell::new(values))))
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        brace: &scanner::Token,
+        entries: &[(expr::Expr, expr::Expr)],
+    ) -> Result<Value, LoxError> {
+        let mut map = IndexMap::new();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            self.check_map_key(&key, brace)?;
+            let value = self.evaluate(value)?;
+            map.insert(key, value);
+        }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn check_map_key(&self, key: &Value, token: &scanner::Token) -> Result<(), LoxError> {
+        key.check_map_key()
+            .map_err(|message| LoxError::runtime(token, String::from(message)))
+    }
+
+    fn list_index(
+        &self,
+        index: Value,
+        len: usize,
+        bracket: &scanner::Token,
+    ) -> Result<usize, LoxError> {
+        let index = match index {
+            Value::Number(num) if num.fract() == 0.0 => num,
+            _ => {
+                return Err(LoxError::runtime(
+                    bracket,
+                    String::from("List index must be an integer"),
+                ))
+            }
         };
-        self.env.assign(tok, value.clone())?;
-        Ok(value.clone())
+        if index < 0.0 {
+            return Err(LoxError::runtime(
+                bracket,
+                format!("List index {} is negative", index),
+            ));
+        }
+        if index >= len as f64 {
+            return Err(LoxError::runtime(
+                bracket,
+                format!(
+                    "List index {} out of range for list of length {}",
+                    index, len
+                ),
+            ));
+        }
+        Ok(index as usize)
     }
-    fn visit_block_stmt(&mut self, statements: Vec<expr::Stmt>) -> Result<(), Error> {
-        let prev_env = self.env.clone();
-        self.env = Environment::new(Some(Box::new(prev_env.clone())));
-        for stmt in statements {
-            match self.execute(stmt) {
-                Err(err) => {
-                    self.env = prev_env;
-                    return Err(err);
+
+    fn visit_index_expr(
+        &mut self,
+        object: &expr::Expr,
+        bracket: &scanner::Token,
+        index: &expr::Expr,
+    ) -> Result<Value, LoxError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        match object {
+            Value::List(list) => {
+                let i = self.list_index(index, list.borrow().len(), bracket)?;
+                let value = list.borrow()[i].clone();
+                Ok(value)
+            }
+            Value::Map(map) => {
+                self.check_map_key(&index, bracket)?;
+                match map.borrow().get(&index) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(LoxError::runtime(
+                        bracket,
+                        format!("Key {} not found in map", index.to_element_string()),
+                    )),
                 }
-                _ => (),
             }
+            _ => Err(LoxError::runtime(
+                bracket,
+                String::from("Only lists and maps can be indexed"),
+            )),
         }
-        match self.env.enclosing.clone() {
-            Some(enc) => self.env = *enc,
-            None => (),
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        object: &expr::Expr,
+        bracket: &scanner::Token,
+        index: &expr::Expr,
+        value: &expr::Expr,
+    ) -> Result<Value, LoxError> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        match object {
+            Value::List(list) => {
+                let i = self.list_index(index, list.borrow().len(), bracket)?;
+                list.borrow_mut()[i] = value.clone();
+                Ok(value)
+            }
+            Value::Map(map) => {
+                self.check_map_key(&index, bracket)?;
+                map.borrow_mut().insert(index, value.clone());
+                Ok(value)
+            }
+            _ => Err(LoxError::runtime(
+                bracket,
+                String::from("Only lists and maps can be indexed"),
+            )),
         }
-        Ok(())
+    }
+
+    fn visit_this_expr(
+        &self,
+        keyword: &scanner::Token,
+        depth: Option<usize>,
+    ) -> Result<Value, LoxError> {
+        self.lookup_variable(keyword, depth)
+    }
+
+    fn visit_super_expr(
+        &self,
+        keyword: &scanner::Token,
+        method: &scanner::Token,
+        depth: Option<usize>,
+    ) -> Result<Value, LoxError> {
+        let distance = match depth {
+            Some(distance) => distance,
+            None => {
+                return Err(LoxError::runtime(
+                    keyword,
+                    String::from("Can't use 'super' outside of a subclass"),
+                ))
+            }
+        };
+        let superclass = match self
+            .env
+            .borrow()
+            .get_at(distance, "super")
+            .map_err(|err| err.or_at(keyword))?
+        {
+            Value::Class(class) => class,
+            _ => {
+                return Err(LoxError::runtime(
+                    keyword,
+                    String::from("Can't use 'super' outside of a subclass"),
+                ))
+            }
+        };
+
+        // `this` is always bound one scope inside the one holding `super`.
+        let object = self.env.borrow().get_at(distance - 1, "this")?;
+
+        match superclass.find_method(&method.lexme) {
+            Some(found) => Ok(Value::Function(Rc::new(found.bind(object)))),
+            None => Err(LoxError::runtime(
+                method,
+                format!("Undefined property '{}'", method.lexme),
+            )
+            .suggest(
+                &method.lexme,
+                superclass.method_names().iter().map(String::as_str),
+            )),
+        }
+    }
+
+    fn visit_return_stmt(&mut self, value: &Option<expr::Expr>) -> Result<(), Unwind> {
+        let value = match value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Nil,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: &expr::Expr,
+        paren: &scanner::Token,
+        args: &[expr::Expr],
+    ) -> Result<Value, LoxError> {
+        let callee = self.evaluate(callee)?;
+
+        let mut arguments = Vec::new();
+        for arg in args {
+            arguments.push(self.evaluate(arg)?);
+        }
+
+        let arity = match &callee {
+            Value::Function(function) => function.arity(),
+            Value::Native(native) => native.arity,
+            Value::Class(class) => class.arity(),
+            _ => {
+                return Err(LoxError::runtime(
+                    paren,
+                    String::from("Can only call functions and classes"),
+                ))
+            }
+        };
+        if arguments.len() != arity {
+            return Err(LoxError::runtime(
+                paren,
+                format!("Expected {} arguments but got {}", arity, arguments.len()),
+            ));
+        }
+
+        // Errors raised inside natives carry no location, so blame the call.
+        if let Value::Native(native) = callee {
+            return (native.func)(self, arguments).map_err(|err| err.or_at(paren));
+        }
+
+        if self.frames.len() >= self.max_depth {
+            return Err(LoxError::runtime(paren, String::from("Stack overflow")));
+        }
+        let name = match &callee {
+            Value::Function(function) => function.name().to_string(),
+            Value::Class(class) => class.name.clone(),
+            _ => unreachable!(),
+        };
+        self.frames.push(Frame::new(Some(name), paren.line));
+        let result = match callee {
+            Value::Function(function) => function.call(self, arguments),
+            Value::Class(class) => class.call(self, arguments),
+            _ => unreachable!(),
+        };
+        // The trace is captured once, by the innermost call it escapes from.
+        let result = result.map_err(|mut err| {
+            if err.trace.is_empty() {
+                err.trace = self.backtrace(err.line()).into_boxed_slice();
+            }
+            err
+        });
+        self.frames.pop();
+        result
     }
 
     fn visit_logical_expr(
         &mut self,
-        left: expr::Expr,
-        op: scanner::Token,
-        right: expr::Expr,
-    ) -> Result<Value, Error> {
+        left: &expr::Expr,
+        op: &scanner::Token,
+        right: &expr::Expr,
+    ) -> Result<Value, LoxError> {
         let left = self.evaluate(left)?;
 
         if op.tok_type == scanner::TokenType::Or {
@@ -261,36 +848,119 @@ impl Interpreter {
                 return Ok(left);
             }
         }
-        return Ok(self.evaluate(right)?);
+        self.evaluate(right)
     }
 
-    fn visit_while(&mut self, condition: expr::Expr, body: expr::Stmt) -> Result<(), Error> {
-        let mut cond = self.evaluate(condition.clone())?;
-        while self.is_truthy(cond.clone()) {
-            self.execute(body.clone())?;
-            cond = self.evaluate(condition.clone())?;
+    fn visit_while(
+        &mut self,
+        condition: &expr::Expr,
+        body: &expr::Stmt,
+        increment: &Option<expr::Expr>,
+    ) -> Result<(), Unwind> {
+        let mut cond = self.evaluate(condition)?;
+        while self.is_truthy(cond) {
+            match self.execute(body) {
+                Ok(()) | Err(Unwind::Continue) => (),
+                Err(Unwind::Break) => break,
+                Err(unwind) => return Err(unwind),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+            cond = self.evaluate(condition)?;
         }
         Ok(())
     }
-    pub fn execute(&mut self, stmt: expr::Stmt) -> Result<(), Error> {
-        match stmt {
-            expr::Stmt::Print(expr) => self.visit_print_stmt(expr),
-            expr::Stmt::Var(name, expr) => self.visit_var_stmt(name, expr),
-            expr::Stmt::Block(exprs) => self.visit_block_stmt(exprs),
-            expr::Stmt::If(condition, then_branch, else_branch) => {
-                self.visit_if_stmt(condition, *then_branch, else_branch)
-            }
-            expr::Stmt::While(condition, body) => self.visit_while(condition, *body),
-            expr::Stmt::Expr(expr) => match expr {
-                expr::Expr::Assign(tok, e) => {
-                    match self.visit_assign_expr(tok, *e) {
-                        Err(msg) => return Err(msg),
-                        _ => (),
-                    }
-                    Ok(())
-                }
-                _ => Err(Error::new(ErrorKind::Other, "Unimplemented STMT")),
-            },
+    // Errors that surface without a location are pinned to the innermost
+    // statement they escaped from.
+    pub fn execute(&mut self, stmt: &expr::Stmt) -> Result<(), Unwind> {
+        let span = stmt.span;
+        if self.stack_exhausted() {
+            return Err(LoxError::runtime_message(String::from("Stack overflow"))
+                .or_within(span)
+                .into());
+        }
+        self.execute_kind(&stmt.kind)
+            .map_err(|unwind| match unwind {
+                Unwind::Error(err) => Unwind::Error(err.or_within(span)),
+                unwind => unwind,
+            })
+    }
+
+    // Runs a statement typed at the REPL. A bare expression gives back its
+    // value so it can be echoed; assignments and other statements don't.
+    pub fn execute_echo(&mut self, stmt: &expr::Stmt) -> Result<Option<Value>, Unwind> {
+        let span = stmt.span;
+        match &stmt.kind {
+            expr::StmtKind::Expr(expr)
+                if !matches!(
+                    expr.kind,
+                    expr::ExprKind::Assign(..)
+                        | expr::ExprKind::Set(..)
+                        | expr::ExprKind::IndexSet(..)
+                ) =>
+            {
+                let value = self.evaluate(expr).map_err(|err| err.or_within(span))?;
+                Ok(Some(value))
+            }
+            _ => {
+                self.execute(stmt)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn execute_kind(&mut self, kind: &expr::StmtKind) -> Result<(), Unwind> {
+        match kind {
+            expr::StmtKind::Print(expr) => Ok(self.visit_print_stmt(expr)?),
+            expr::StmtKind::Var(name, expr) => Ok(self.visit_var_stmt(name, expr)?),
+            expr::StmtKind::Block(exprs) => self.visit_block_stmt(exprs),
+            expr::StmtKind::If(condition, then_branch, else_branch) => {
+                self.visit_if_stmt(condition, then_branch, else_branch)
+            }
+            expr::StmtKind::While(condition, body, increment, _) => {
+                self.visit_while(condition, body, increment)
+            }
+            expr::StmtKind::Break(_) => Err(Unwind::Break),
+            expr::StmtKind::Continue(_) => Err(Unwind::Continue),
+            expr::StmtKind::Function(declaration) => self.visit_function_stmt(declaration),
+            expr::StmtKind::Class(name, superclass, methods) => {
+                self.visit_class_stmt(name, superclass, methods)
+            }
+            expr::StmtKind::Return(_, value) => self.visit_return_stmt(value),
+            expr::StmtKind::Expr(expr) => {
+                self.evaluate(expr)?;
+                Ok(())
+            }
         }
     }
 }
+
+// Describes a binary operator applied to operands it doesn't support.
+fn binary_type_error(op: &scanner::Token, left: &Value, right: &Value) -> LoxError {
+    let expected = match op.tok_type {
+        scanner::TokenType::Plus
+        | scanner::TokenType::Greater
+        | scanner::TokenType::GreaterEqual
+        | scanner::TokenType::Less
+        | scanner::TokenType::LessEqual => "two numbers or two strings",
+        _ => "two numbers",
+    };
+    LoxError::runtime(
+        op,
+        format!(
+            "Operands of '{}' must be {}, got {} and {}",
+            op.lexme,
+            expected,
+            left.type_name(),
+            right.type_name()
+        ),
+    )
+}
+
+// The address of a local in the calling frame. The distance between two of
+// these is how much stack was used in between.
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
@@ -0,0 +1,324 @@
+use crate::expr;
+use crate::scanner;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WarningKind {
+    UnusedVariable,
+    Shadowing,
+    UnreachableCode,
+    ConstantCondition,
+}
+
+impl WarningKind {
+    pub const ALL: [WarningKind; 4] = [
+        WarningKind::UnusedVariable,
+        WarningKind::Shadowing,
+        WarningKind::UnreachableCode,
+        WarningKind::ConstantCondition,
+    ];
+
+    // The name used to silence this category on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable => "unused-variable",
+            WarningKind::Shadowing => "shadowing",
+            WarningKind::UnreachableCode => "unreachable-code",
+            WarningKind::ConstantCondition => "constant-condition",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WarningKind> {
+        WarningKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    pub span: scanner::Span,
+    pub note: Option<String>,
+    pub help: Option<String>,
+}
+
+struct Local {
+    span: scanner::Span,
+    used: bool,
+    // Only `var` locals are reported when unused; parameters, functions and
+    // classes are not.
+    check_unused: bool,
+}
+
+// Looks over a resolved program for code that is valid but probably not
+// what was meant. Unlike the resolver it never stops the program running.
+pub struct Linter {
+    // The first scope holds the globals, which are checked for shadowing but
+    // never reported as unused.
+    scopes: Vec<HashMap<String, Local>>,
+    allowed: Vec<WarningKind>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    pub fn new(allowed: &[WarningKind]) -> Linter {
+        Linter {
+            scopes: Vec::new(),
+            allowed: allowed.to_vec(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // Returns the warnings for a program in source order.
+    pub fn check(mut self, stmts: &[expr::Stmt]) -> Vec<Warning> {
+        self.scopes.push(HashMap::new());
+        self.check_stmts(stmts);
+        self.scopes.pop();
+
+        self.warnings.sort_by_key(|warning| warning.span.start);
+        self.warnings
+    }
+
+    fn warn(
+        &mut self,
+        kind: WarningKind,
+        span: scanner::Span,
+        message: String,
+        note: Option<String>,
+        help: Option<String>,
+    ) {
+        if self.allowed.contains(&kind) {
+            return;
+        }
+        self.warnings.push(Warning {
+            kind,
+            message,
+            span,
+            note,
+            help,
+        });
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("Linter scope underflow");
+        for (name, local) in scope {
+            if local.check_unused && !local.used && !name.starts_with('_') {
+                self.warn(
+                    WarningKind::UnusedVariable,
+                    local.span,
+                    format!("unused variable `{}`", name),
+                    None,
+                    Some(format!(
+                        "if this is intentional, prefix it with an underscore: `_{}`",
+                        name
+                    )),
+                );
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &scanner::Token, check_unused: bool) {
+        let innermost = self.scopes.len() - 1;
+        let outer = self.scopes[..innermost]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexme))
+            .map(|local| local.span);
+        if let Some(outer) = outer {
+            self.warn(
+                WarningKind::Shadowing,
+                name.span(),
+                format!("`{}` shadows a variable from an outer scope", name.lexme),
+                Some(format!(
+                    "the shadowed `{}` is declared on line {}",
+                    name.lexme, outer.line
+                )),
+                None,
+            );
+        }
+
+        let local = Local {
+            span: name.span(),
+            used: false,
+            check_unused: check_unused && innermost > 0,
+        };
+        self.scopes[innermost].insert(name.lexme.clone(), local);
+    }
+
+    fn read(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(local) = scope.get_mut(name) {
+                local.used = true;
+                return;
+            }
+        }
+    }
+
+    // Checks a list of statements, flagging the first one that can never be
+    // reached. Returns whether the list always exits early.
+    fn check_stmts(&mut self, stmts: &[expr::Stmt]) -> bool {
+        let mut diverges = false;
+        let mut reported = false;
+        for stmt in stmts {
+            if diverges && !reported {
+                self.warn(
+                    WarningKind::UnreachableCode,
+                    stmt.span,
+                    String::from("unreachable statement"),
+                    Some(String::from(
+                        "every path before it ends in `return`, `break` or `continue`",
+                    )),
+                    None,
+                );
+                reported = true;
+            }
+            diverges |= self.check_stmt(stmt);
+        }
+        diverges
+    }
+
+    fn check_function(&mut self, function: &expr::Function) {
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param, false);
+        }
+        self.check_stmts(&function.body);
+        self.end_scope();
+    }
+
+    fn check_condition(&mut self, keyword: &str, condition: &expr::Expr) {
+        let mut inner = condition;
+        while let expr::ExprKind::Grouping(grouped) = &inner.kind {
+            inner = grouped;
+        }
+        let truthy = match &inner.kind {
+            expr::ExprKind::Literal(expr::Literal::False | expr::Literal::Nil) => false,
+            expr::ExprKind::Literal(_) => true,
+            _ => return,
+        };
+        // `while (true)` and `for (;;)` are how Lox spells an infinite loop.
+        if keyword != "if" && truthy {
+            return;
+        }
+        self.warn(
+            WarningKind::ConstantCondition,
+            condition.span,
+            format!("`{}` condition is always {}", keyword, truthy),
+            None,
+            None,
+        );
+    }
+
+    // Returns whether the statement always exits early.
+    fn check_stmt(&mut self, stmt: &expr::Stmt) -> bool {
+        match &stmt.kind {
+            expr::StmtKind::Print(expr) | expr::StmtKind::Expr(expr) => {
+                self.check_expr(expr);
+                false
+            }
+            expr::StmtKind::Var(name, initializer) => {
+                self.check_expr(initializer);
+                self.declare(name, true);
+                false
+            }
+            expr::StmtKind::Block(statements) => {
+                self.begin_scope();
+                let diverges = self.check_stmts(statements);
+                self.end_scope();
+                diverges
+            }
+            expr::StmtKind::If(condition, then_branch, else_branch) => {
+                self.check_condition("if", condition);
+                self.check_expr(condition);
+                let then_diverges = self.check_stmt(then_branch);
+                match else_branch {
+                    Some(else_branch) => self.check_stmt(else_branch) && then_diverges,
+                    None => false,
+                }
+            }
+            expr::StmtKind::While(condition, body, increment, from_for) => {
+                let keyword = if *from_for { "for" } else { "while" };
+                self.check_condition(keyword, condition);
+                self.check_expr(condition);
+                self.check_stmt(body);
+                if let Some(increment) = increment {
+                    self.check_expr(increment);
+                }
+                false
+            }
+            expr::StmtKind::Break(_) | expr::StmtKind::Continue(_) => true,
+            expr::StmtKind::Return(_, value) => {
+                if let Some(value) = value {
+                    self.check_expr(value);
+                }
+                true
+            }
+            expr::StmtKind::Function(function) => {
+                self.declare(&function.name, false);
+                self.check_function(function);
+                false
+            }
+            expr::StmtKind::Class(name, superclass, methods) => {
+                self.declare(name, false);
+                if let Some(superclass) = superclass {
+                    self.check_expr(superclass);
+                }
+                for method in methods {
+                    self.check_function(method);
+                }
+                false
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &expr::Expr) {
+        match &expr.kind {
+            expr::ExprKind::Literal(_) | expr::ExprKind::This(..) | expr::ExprKind::Super(..) => (),
+            expr::ExprKind::Grouping(e) | expr::ExprKind::Unary(_, e) => self.check_expr(e),
+            expr::ExprKind::Binary(left, _, right) | expr::ExprKind::Logical(left, _, right) => {
+                self.check_expr(left);
+                self.check_expr(right);
+            }
+            expr::ExprKind::Identifier(name, _) => self.read(&name.lexme),
+            expr::ExprKind::Assign(_, value, _) => self.check_expr(value),
+            expr::ExprKind::Call(callee, _, args) => {
+                self.check_expr(callee);
+                for arg in args {
+                    self.check_expr(arg);
+                }
+            }
+            expr::ExprKind::Get(object, _) => self.check_expr(object),
+            expr::ExprKind::Set(object, _, value) => {
+                self.check_expr(object);
+                self.check_expr(value);
+            }
+            expr::ExprKind::List(elements) => {
+                for element in elements {
+                    self.check_expr(element);
+                }
+            }
+            expr::ExprKind::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.check_expr(key);
+                    self.check_expr(value);
+                }
+            }
+            expr::ExprKind::Index(object, _, index) => {
+                self.check_expr(object);
+                self.check_expr(index);
+            }
+            expr::ExprKind::IndexSet(object, _, index, value) => {
+                self.check_expr(object);
+                self.check_expr(index);
+                self.check_expr(value);
+            }
+        }
+    }
+}
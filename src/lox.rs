@@ -1,62 +1,386 @@
-use crate::interpreter::Interpreter;
+use crate::diagnostics::{Diagnostic, ErrorFormat, Severity};
+use crate::error::{LoxError, Phase};
+use crate::function::NativeFunction;
+use crate::interpreter::{Interpreter, Unwind, Value, DEFAULT_MAX_DEPTH, DEFAULT_STACK_SIZE};
+use crate::lint::{Linter, WarningKind};
+use crate::natives;
 use crate::parser::Parser;
-use crate::scanner::Scanner;
-use log::{error, info};
+use crate::resolver::Resolver;
+use crate::scanner::{Scanner, TokenType};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::env;
 use std::fs::read;
-use std::io;
-use std::io::prelude::*;
-use std::io::Result;
-pub struct Lox;
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+
+// Exit statuses from sysexits.h, as used by the reference Lox.
+pub const EX_USAGE: i32 = 64;
+pub const EX_DATAERR: i32 = 65;
+pub const EX_NOINPUT: i32 = 66;
+pub const EX_SOFTWARE: i32 = 70;
+
+pub struct Lox {
+    max_depth: usize,
+    stack_size: usize,
+    allowed: Vec<WarningKind>,
+    error_format: ErrorFormat,
+    // Every source run so far, as a name and its text, indexed by the id
+    // their spans carry. A REPL function can fail long after it was typed.
+    sources: Vec<(String, String)>,
+    // Functions implemented in Rust, defined in every interpreter made, so
+    // `:reset` keeps them.
+    natives: Vec<Rc<NativeFunction>>,
+}
 
 impl Lox {
     pub fn new() -> Lox {
-        Lox
+        let mut lox = Lox {
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack_size: DEFAULT_STACK_SIZE,
+            allowed: Vec::new(),
+            error_format: ErrorFormat::Human,
+            sources: Vec::new(),
+            natives: Vec::new(),
+        };
+        natives::define_builtins(&mut lox);
+        lox
+    }
+
+    // Registers a Rust closure as a global Lox function. Embedders can use
+    // this to expose their own builtins next to the standard ones.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, func: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, LoxError> + 'static,
+    {
+        let native = NativeFunction::new(name.to_string(), arity, Rc::new(func));
+        self.natives.push(Rc::new(native));
+    }
+
+    pub fn set_error_format(&mut self, error_format: ErrorFormat) {
+        self.error_format = error_format;
+    }
+
+    // Shows a diagnostic against the source its span is in. One without a
+    // span is only labelled with `file`.
+    fn report(&self, diagnostic: &Diagnostic, file: &str) {
+        match diagnostic
+            .span
+            .and_then(|span| self.sources.get(span.source))
+        {
+            Some((name, source)) => diagnostic.emit(name, source, self.error_format),
+            None => diagnostic.emit(file, "", self.error_format),
+        }
+    }
+
+    // Keeps a source for reporting errors, returning the id for its spans.
+    fn add_source(&mut self, name: &str, source: &str) -> usize {
+        self.sources
+            .push((String::from(name), String::from(source)));
+        self.sources.len() - 1
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // The stack size of the thread the programs run on.
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_size = stack_size;
+    }
+
+    // Stops warnings of this kind from being reported.
+    pub fn allow(&mut self, kind: WarningKind) {
+        self.allowed.push(kind);
+    }
+
+    // Runs a whole program, printing any warnings about it to stderr first.
+    // `name` is the file the source came from, for those warnings. Syntax
+    // errors are all returned together; any other phase stops at its first
+    // error.
+    pub fn run(&mut self, name: &str, source: String) -> Result<(), Vec<LoxError>> {
+        let mut interp = self.interpreter();
+        self.execute(&mut interp, name, source, false)
     }
 
-    pub fn run(&self, source: String) -> Result<()> {
+    fn interpreter(&self) -> Interpreter {
+        let mut interp = Interpreter::new();
+        interp.set_max_depth(self.max_depth);
+        interp.set_stack_size(self.stack_size);
+        for native in &self.natives {
+            interp.define_native(native.clone());
+        }
+        interp
+    }
+
+    // Runs source in an existing interpreter, so globals from earlier runs
+    // are still defined. At the REPL (`repl` set) the value of each bare
+    // expression is printed, and the final one doesn't need its `;`.
+    fn execute(
+        &mut self,
+        interp: &mut Interpreter,
+        name: &str,
+        source: String,
+        repl: bool,
+    ) -> Result<(), Vec<LoxError>> {
+        let source_id = self.add_source(name, &source);
         let mut scanner = Scanner::new(source);
-        match scanner.scan_tokens() {
-            Err(err_msg) => {
-                error!("{}", err_msg);
-                return Ok(());
+        scanner.set_source_id(source_id);
+        scanner.scan_tokens()?;
+
+        let mut parser = Parser::new(scanner.tokens);
+        parser.set_repl(repl);
+        let mut stmts = parser.parse()?;
+
+        let mut resolver = Resolver::new();
+        resolver.resolve(&mut stmts)?;
+
+        for warning in Linter::new(&self.allowed).check(&stmts) {
+            self.report(&Diagnostic::from(&warning), name);
+        }
+
+        for stmt in stmts {
+            let result = if repl {
+                interp.execute_echo(&stmt).map(|value| match value {
+                    Some(Value::Nil) | None => (),
+                    Some(value) => println!("{}", value),
+                })
+            } else {
+                interp.execute(&stmt)
+            };
+            match result {
+                Ok(()) => (),
+                Err(Unwind::Error(err)) => return Err(vec![err]),
+                Err(unwind) => return Err(vec![LoxError::runtime_message(unwind.to_string())]),
             }
-            _ => (),
         }
 
-        // info!("\n\ttokens: {:?}", scanner.tokens);
+        Ok(())
+    }
 
-        let mut parser = Parser::new(scanner.tokens);
-        let stmts = match parser.parse() {
-            Ok(stmts) => stmts,
-            Err(err_msg) => {
-                error!("{}", err_msg);
-                return Ok(());
+    // Runs a script and returns the process exit status: 65 if it doesn't
+    // compile, 70 if it fails while running.
+    pub fn run_file(&mut self, path: &str) -> i32 {
+        let source = match self.read_source(path) {
+            Ok(source) => source,
+            Err(code) => return code,
+        };
+
+        match self.run(path, source) {
+            Ok(()) => 0,
+            Err(errors) => {
+                for err in &errors {
+                    self.report(&Diagnostic::from(err), path);
+                }
+                if errors.iter().any(|err| err.phase == Phase::Runtime) {
+                    EX_SOFTWARE
+                } else {
+                    EX_DATAERR
+                }
+            }
+        }
+    }
+
+    // Reads a script, reporting why it can't be and the exit status to use.
+    fn read_source(&self, path: &str) -> Result<String, i32> {
+        let source = match read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                let message = format!("can't read '{}': {}", path, err);
+                let diagnostic = Diagnostic::new(Severity::Error, message).with_code("io-error");
+                self.report(&diagnostic, path);
+                return Err(EX_NOINPUT);
             }
         };
-        // info!("\n\tstmts: {:?}", stmts);
+        match String::from_utf8(source) {
+            Ok(source) => Ok(source),
+            Err(_) => {
+                let message = format!("'{}' is not valid UTF-8", path);
+                let diagnostic = Diagnostic::new(Severity::Error, message).with_code("io-error");
+                self.report(&diagnostic, path);
+                Err(EX_DATAERR)
+            }
+        }
+    }
 
-        let mut interp = Interpreter::new();
-        for stmt in stmts {
-            match interp.execute(stmt) {
-                Err(err_msg) => {
-                    error!("{}", err_msg)
+    // An interactive session. Everything typed shares one interpreter, and
+    // input carries on over several lines while brackets are left open.
+    pub fn run_prompt(&mut self) {
+        let mut editor = match DefaultEditor::new() {
+            Ok(editor) => editor,
+            Err(err) => {
+                let message = format!("can't start the prompt: {}", err);
+                let diagnostic = Diagnostic::new(Severity::Error, message).with_code("io-error");
+                self.report(&diagnostic, "<stdin>");
+                return;
+            }
+        };
+        let history = history_path();
+        if let Some(history) = &history {
+            // There's no history yet the first time round.
+            let _ = editor.load_history(history);
+        }
+
+        let mut interp = self.interpreter();
+        let mut source = String::new();
+        loop {
+            let prompt = if source.is_empty() { "> " } else { "... " };
+            match editor.readline(prompt) {
+                // Commands are a single line, and only where input starts.
+                Ok(line) if source.is_empty() && line.trim_start().starts_with(':') => {
+                    let _ = editor.add_history_entry(line.trim());
+                    self.command(&mut interp, line.trim());
+                    continue;
+                }
+                Ok(line) => {
+                    source.push_str(&line);
+                    source.push('\n');
+                }
+                // Ctrl-C throws away the input so far, like most shells.
+                Err(ReadlineError::Interrupted) => {
+                    source.clear();
+                    continue;
                 }
-                _ => (),
+                Err(ReadlineError::Eof) => break,
+                Err(err) => {
+                    let message = format!("can't read input: {}", err);
+                    let diagnostic =
+                        Diagnostic::new(Severity::Error, message).with_code("io-error");
+                    self.report(&diagnostic, "<stdin>");
+                    break;
+                }
+            }
+            if is_incomplete(&source) {
+                continue;
             }
+
+            let input = mem::take(&mut source);
+            if input.trim().is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(input.trim_end());
+            self.run_input(&mut interp, "<stdin>", input, true);
         }
-        info!("\n\tenv: {:?}", interp.env);
 
-        Ok(())
+        if let Some(history) = &history {
+            let _ = editor.save_history(history);
+        }
     }
-    pub fn run_file(&self, path: &str) -> Result<()> {
-        let source = String::from_utf8(read(path)?).expect("Found invalid UTF-8");
-        self.run(source)
+
+    // Runs input in the session, reporting any errors against the source
+    // they come from, which may be an earlier input.
+    fn run_input(&mut self, interp: &mut Interpreter, name: &str, source: String, repl: bool) {
+        if let Err(errors) = self.execute(interp, name, source, repl) {
+            for err in &errors {
+                self.report(&Diagnostic::from(err), name);
+            }
+        }
     }
 
-    pub fn run_prompt(&mut self) {
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            self.run(line.unwrap()).unwrap();
+    // Handles a REPL command such as `:env` or `:load file.lox`.
+    fn command(&mut self, interp: &mut Interpreter, line: &str) {
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        match name {
+            ":help" => println!("{}", COMMANDS),
+            ":env" => print!("{}", interp.env.borrow()),
+            ":reset" => *interp = self.interpreter(),
+            ":tokens" | ":ast" | ":load" | ":time" if arg.is_empty() => {
+                let message = format!("`{}` needs an argument", name);
+                let diagnostic = Diagnostic::new(Severity::Error, message)
+                    .with_help(Some(String::from("`:help` lists the commands")));
+                self.report(&diagnostic, "<stdin>");
+            }
+            ":tokens" => self.show_tokens(arg),
+            ":ast" => self.show_ast(arg),
+            ":load" => {
+                if let Ok(source) = self.read_source(arg) {
+                    self.run_input(interp, arg, source, false);
+                }
+            }
+            ":time" => {
+                let start = Instant::now();
+                self.run_input(interp, "<stdin>", String::from(arg), true);
+                println!("took {:?}", start.elapsed());
+            }
+            _ => {
+                let message = format!("unknown command `{}`", name);
+                let diagnostic = Diagnostic::new(Severity::Error, message)
+                    .with_help(Some(String::from("`:help` lists the commands")));
+                self.report(&diagnostic, "<stdin>");
+            }
+        }
+    }
+
+    fn show_tokens(&mut self, source: &str) {
+        let mut scanner = Scanner::new(String::from(source));
+        scanner.set_source_id(self.add_source("<stdin>", source));
+        if let Err(errors) = scanner.scan_tokens() {
+            for err in &errors {
+                self.report(&Diagnostic::from(err), "<stdin>");
+            }
+        }
+        for token in &scanner.tokens {
+            let position = format!("{}:{}", token.line, token.column);
+            let kind = format!("{:?}", token.tok_type);
+            println!("{:<6} {:<14} {}", position, kind, token.lexme);
         }
     }
+
+    fn show_ast(&mut self, source: &str) {
+        let mut scanner = Scanner::new(String::from(source));
+        scanner.set_source_id(self.add_source("<stdin>", source));
+        let stmts = scanner.scan_tokens().and_then(|()| {
+            let mut parser = Parser::new(scanner.tokens);
+            parser.set_repl(true);
+            parser.parse()
+        });
+        match stmts {
+            Ok(stmts) => {
+                for stmt in &stmts {
+                    println!("{}", stmt);
+                }
+            }
+            Err(errors) => {
+                for err in &errors {
+                    self.report(&Diagnostic::from(err), "<stdin>");
+                }
+            }
+        }
+    }
+}
+
+const COMMANDS: &str = "\
+:env            show every variable in scope
+:tokens <code>  show the tokens the scanner produces for <code>
+:ast <code>     show the syntax tree the parser builds for <code>
+:load <file>    run a script into this session
+:reset          forget every variable defined so far
+:time <code>    run <code> and report how long it took
+:help           show this list";
+
+// Input is incomplete while a string or any bracket is still open.
+fn is_incomplete(source: &str) -> bool {
+    let mut scanner = Scanner::new(String::from(source));
+    if let Err(errors) = scanner.scan_tokens() {
+        return errors.iter().any(|err| err.unterminated);
+    }
+    let mut depth = 0;
+    for token in &scanner.tokens {
+        match token.tok_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+// REPL history is kept in ~/.rlox_history.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".rlox_history"))
 }
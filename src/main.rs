@@ -1,26 +1,132 @@
 extern crate clap;
-use clap::{App, Arg};
+use clap::{App, Arg, ErrorKind};
 
+mod class;
+mod diagnostics;
 mod environment;
+mod error;
 mod expr;
+mod function;
 mod interpreter;
+mod lint;
 mod lox;
+mod natives;
 mod parser;
+mod resolver;
 mod scanner;
 
+use std::process;
+use std::thread;
+
+// Each Lox call nests several tree-walking Rust calls, so the interpreter
+// runs on a thread with a large stack. It's only reserved, not committed, up
+// front, and the interpreter checks how much is left as it recurses.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn main() {
     env_logger::init();
     let matches = App::new("rlox")
         .version("1.0")
         .about("Interpreter for rlox language")
         .arg(Arg::with_name("SCRIPT").help("Script for interpreter"))
-        .get_matches();
+        .arg(
+            Arg::with_name("max-depth")
+                .long("max-depth")
+                .value_name("FRAMES")
+                .help("Maximum call depth before a stack overflow is reported")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("allow")
+                .short("A")
+                .long("allow")
+                .value_name("WARNING")
+                .help("Silence a category of warning (unused-variable, shadowing, unreachable-code, constant-condition)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .help("How to print errors and warnings")
+                .possible_values(&["human", "json"])
+                .default_value("human"),
+        )
+        .get_matches_safe();
+    let matches = match matches {
+        Ok(matches) => matches,
+        Err(err)
+            if err.kind == ErrorKind::HelpDisplayed || err.kind == ErrorKind::VersionDisplayed =>
+        {
+            err.exit()
+        }
+        Err(err) => {
+            eprintln!("{}", err.message);
+            process::exit(lox::EX_USAGE);
+        }
+    };
 
-    let mut lox = lox::Lox::new();
-    if matches.is_present("SCRIPT") {
-        let path = matches.value_of("SCRIPT").unwrap();
-        lox.run_file(path).unwrap();
-    } else {
-        lox.run_prompt();
+    let max_depth = match matches.value_of("max-depth") {
+        Some(depth) => match depth.parse() {
+            Ok(depth) if depth <= interpreter::MAX_DEPTH_LIMIT => depth,
+            Ok(_) => {
+                eprintln!(
+                    "error: --max-depth can be at most {}, got '{}'",
+                    interpreter::MAX_DEPTH_LIMIT,
+                    depth
+                );
+                process::exit(lox::EX_USAGE);
+            }
+            Err(_) => {
+                eprintln!("error: --max-depth expects a number, got '{}'", depth);
+                process::exit(lox::EX_USAGE);
+            }
+        },
+        None => interpreter::DEFAULT_MAX_DEPTH,
+    };
+    let mut allowed = Vec::new();
+    for name in matches.values_of("allow").into_iter().flatten() {
+        match lint::WarningKind::from_name(name) {
+            Some(kind) => allowed.push(kind),
+            None => {
+                eprintln!("error: unknown warning category '{}'", name);
+                process::exit(lox::EX_USAGE);
+            }
+        }
     }
+    let error_format = matches
+        .value_of("error-format")
+        .and_then(diagnostics::ErrorFormat::from_name)
+        .unwrap_or(diagnostics::ErrorFormat::Human);
+    let script = matches.value_of("SCRIPT").map(String::from);
+
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut lox = lox::Lox::new();
+            lox.set_max_depth(max_depth);
+            lox.set_stack_size(STACK_SIZE);
+            lox.set_error_format(error_format);
+            for kind in allowed {
+                lox.allow(kind);
+            }
+            match script {
+                Some(path) => lox.run_file(&path),
+                None => {
+                    lox.run_prompt();
+                    0
+                }
+            }
+        });
+    let code = match runner.map(|handle| handle.join()) {
+        Ok(Ok(code)) => code,
+        Ok(Err(_)) => lox::EX_SOFTWARE,
+        Err(err) => {
+            eprintln!("error: can't start the interpreter: {}", err);
+            lox::EX_SOFTWARE
+        }
+    };
+    process::exit(code);
 }
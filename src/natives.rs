@@ -0,0 +1,126 @@
+use crate::error::LoxError;
+use crate::interpreter::{Interpreter, Value};
+use crate::lox::Lox;
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The standard library every interpreter starts with.
+pub fn define_builtins(lox: &mut Lox) {
+    lox.define_native("clock", 0, clock);
+    lox.define_native("len", 1, len);
+    lox.define_native("str", 1, str);
+    lox.define_native("push", 2, push);
+    lox.define_native("pop", 1, pop);
+    lox.define_native("keys", 1, keys);
+    lox.define_native("values", 1, values);
+    lox.define_native("has", 2, has);
+    lox.define_native("remove", 2, remove);
+}
+
+fn clock(_interp: &mut Interpreter, _args: Vec<Value>) -> Result<Value, LoxError> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => Ok(Value::Number(elapsed.as_secs_f64())),
+        Err(err) => Err(LoxError::runtime_message(format!(
+            "clock() failed: {}",
+            err
+        ))),
+    }
+}
+
+fn len(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    match &args[0] {
+        Value::List(list) => Ok(Value::Number(list.borrow().len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.borrow().len() as f64)),
+        Value::String(string) => Ok(Value::Number(string.chars().count() as f64)),
+        _ => Err(LoxError::runtime_message(String::from(
+            "len() expects a list, a map or a string",
+        ))),
+    }
+}
+
+fn str(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    Ok(Value::String(args[0].to_string()))
+}
+
+fn push(_interp: &mut Interpreter, mut args: Vec<Value>) -> Result<Value, LoxError> {
+    let value = mem::replace(&mut args[1], Value::Nil);
+    match &args[0] {
+        Value::List(list) => {
+            list.borrow_mut().push(value);
+            Ok(Value::Nil)
+        }
+        _ => Err(LoxError::runtime_message(String::from(
+            "push() expects a list as its first argument",
+        ))),
+    }
+}
+
+fn pop(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    match &args[0] {
+        Value::List(list) => match list.borrow_mut().pop() {
+            Some(value) => Ok(value),
+            None => Err(LoxError::runtime_message(String::from(
+                "pop() called on an empty list",
+            ))),
+        },
+        _ => Err(LoxError::runtime_message(String::from(
+            "pop() expects a list",
+        ))),
+    }
+}
+
+fn keys(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    match &args[0] {
+        Value::Map(map) => {
+            let keys = map.borrow().keys().cloned().collect();
+            Ok(Value::List(Rc::new(RefCell::new(keys))))
+        }
+        _ => Err(LoxError::runtime_message(String::from(
+            "keys() expects a map",
+        ))),
+    }
+}
+
+fn values(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    match &args[0] {
+        Value::Map(map) => {
+            let values = map.borrow().values().cloned().collect();
+            Ok(Value::List(Rc::new(RefCell::new(values))))
+        }
+        _ => Err(LoxError::runtime_message(String::from(
+            "values() expects a map",
+        ))),
+    }
+}
+
+fn has(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    check_map_key(&args[1])?;
+    match &args[0] {
+        Value::Map(map) => Ok(Value::Bool(map.borrow().contains_key(&args[1]))),
+        _ => Err(LoxError::runtime_message(String::from(
+            "has() expects a map as its first argument",
+        ))),
+    }
+}
+
+// Returns the removed value, or nil when the key was absent.
+fn remove(_interp: &mut Interpreter, args: Vec<Value>) -> Result<Value, LoxError> {
+    check_map_key(&args[1])?;
+    match &args[0] {
+        Value::Map(map) => match map.borrow_mut().shift_remove(&args[1]) {
+            Some(value) => Ok(value),
+            None => Ok(Value::Nil),
+        },
+        _ => Err(LoxError::runtime_message(String::from(
+            "remove() expects a map as its first argument",
+        ))),
+    }
+}
+
+// Rejects keys no map could hold, the same as indexing does.
+fn check_map_key(key: &Value) -> Result<(), LoxError> {
+    key.check_map_key()
+        .map_err(|message| LoxError::runtime_message(String::from(message)))
+}
@@ -1,12 +1,15 @@
+use crate::error::LoxError;
 use crate::expr;
 
 use crate::scanner;
+use std::rc::Rc;
 
-use std::io;
 pub struct Parser {
     current: usize,
     tokens: Vec<scanner::Token>,
-    pub exprs: Vec<expr::Expr>,
+    errors: Vec<LoxError>,
+    // At the REPL the `;` after a final expression statement may be left off.
+    repl: bool,
 }
 
 /*
@@ -26,11 +29,16 @@ impl Parser {
     pub fn new(tokens: Vec<scanner::Token>) -> Parser {
         Parser {
             current: 0,
-            tokens: tokens,
-            exprs: Vec::new(),
+            tokens,
+            errors: Vec::new(),
+            repl: false,
         }
     }
 
+    pub fn set_repl(&mut self, repl: bool) {
+        self.repl = repl;
+    }
+
     fn peek(&self) -> &scanner::Token {
         self.tokens.get(self.current).unwrap()
     }
@@ -76,34 +84,112 @@ impl Parser {
         false
     }
 
-    fn consume(&mut self, ty: scanner::TokenType, msg: &str) -> Result<scanner::Token, io::Error> {
+    // The span from `start` up to the last consumed token.
+    fn span_from(&self, start: scanner::Span) -> scanner::Span {
+        start.to(self.previous().span())
+    }
+
+    fn consume(&mut self, ty: scanner::TokenType, msg: &str) -> Result<scanner::Token, LoxError> {
         if self.check(ty) {
-            return Ok(self.advance());
+            Ok(self.advance())
         } else {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("{}. received {:?}", msg, ty),
-            ));
+            Err(LoxError::parse(self.peek(), String::from(msg)))
         }
     }
 
-    fn primary(&mut self) -> Result<expr::Expr, io::Error> {
+    fn primary(&mut self) -> Result<expr::Expr, LoxError> {
+        let start = self.peek().span();
         if self.match_one_of(vec![scanner::TokenType::False]) {
-            return Ok(expr::Expr::Literal(expr::Literal::False));
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Literal(expr::Literal::False),
+                start,
+            ));
         }
         if self.match_one_of(vec![scanner::TokenType::True]) {
-            return Ok(expr::Expr::Literal(expr::Literal::True));
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Literal(expr::Literal::True),
+                start,
+            ));
         }
 
         if self.match_one_of(vec![scanner::TokenType::Nil]) {
-            return Ok(expr::Expr::Literal(expr::Literal::Nil));
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Literal(expr::Literal::Nil),
+                start,
+            ));
         }
 
         if self.match_one_of(vec![scanner::TokenType::Number, scanner::TokenType::String]) {
-            return Ok(expr::Expr::Literal(self.previous().literal.clone()));
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Literal(self.previous().literal.clone()),
+                start,
+            ));
+        }
+        if self.match_one_of(vec![scanner::TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(scanner::TokenType::Dot, "Expect '.' after 'super'")?;
+            let method = self.consume(
+                scanner::TokenType::Identifier,
+                "Expect superclass method name",
+            )?;
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Super(keyword, method, None),
+                self.span_from(start),
+            ));
+        }
+        if self.match_one_of(vec![scanner::TokenType::This]) {
+            return Ok(expr::Expr::new(
+                expr::ExprKind::This(self.previous(), None),
+                start,
+            ));
         }
         if self.match_one_of(vec![scanner::TokenType::Identifier]) {
-            return Ok(expr::Expr::Identifier(self.previous()));
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Identifier(self.previous(), None),
+                start,
+            ));
+        }
+        if self.match_one_of(vec![scanner::TokenType::LeftBracket]) {
+            let mut elements = Vec::new();
+            if !self.check(scanner::TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.matches(scanner::TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(
+                scanner::TokenType::RightBracket,
+                "Expect ']' after list elements",
+            )?;
+            return Ok(expr::Expr::new(
+                expr::ExprKind::List(elements),
+                self.span_from(start),
+            ));
+        }
+        if self.match_one_of(vec![scanner::TokenType::LeftBrace]) {
+            let brace = self.previous();
+            let mut entries = Vec::new();
+            if !self.check(scanner::TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(scanner::TokenType::Colon, "Expect ':' after map key")?;
+                    let value = self.expression()?;
+                    entries.push((key, value));
+                    if !self.matches(scanner::TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(
+                scanner::TokenType::RightBrace,
+                "Expect '}' after map entries",
+            )?;
+            return Ok(expr::Expr::new(
+                expr::ExprKind::Map(brace, entries),
+                self.span_from(start),
+            ));
         }
         if self.match_one_of(vec![scanner::TokenType::LeftParen]) {
             let expr = Box::new(self.expression()?);
@@ -111,27 +197,94 @@ impl Parser {
                 scanner::TokenType::RightParen,
                 "Expect ')' after expression.",
             )?;
-            return Ok(expr::Expr::Grouping(expr));
+            Ok(expr::Expr::new(
+                expr::ExprKind::Grouping(expr),
+                self.span_from(start),
+            ))
         } else {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Invalid expression: {:?}", self.peek().tok_type),
-            ));
+            Err(LoxError::parse(
+                self.peek(),
+                String::from("Expect expression"),
+            ))
         }
     }
 
-    fn unary(&mut self) -> Result<expr::Expr, io::Error> {
-        if self.match_one_of(vec![scanner::TokenType::Minus, scanner::TokenType::Bang]) {
+    fn unary(&mut self) -> Result<expr::Expr, LoxError> {
+        if self.match_one_of(vec![
+            scanner::TokenType::Minus,
+            scanner::TokenType::Bang,
+            scanner::TokenType::Interpolate,
+        ]) {
             let op = self.previous().clone();
             let right = Box::new(self.unary()?);
+            let span = op.span().to(right.span);
+
+            return Ok(expr::Expr::new(expr::ExprKind::Unary(op, right), span));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<expr::Expr, LoxError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matches(scanner::TokenType::LeftParen) {
+                expr = self.finish_call(expr)?;
+            } else if self.matches(scanner::TokenType::Dot) {
+                let name = self.consume(
+                    scanner::TokenType::Identifier,
+                    "Expect property name after '.'",
+                )?;
+                let span = expr.span.to(name.span());
+                expr = expr::Expr::new(expr::ExprKind::Get(Box::new(expr), name), span);
+            } else if self.matches(scanner::TokenType::LeftBracket) {
+                let index = self.expression()?;
+                let bracket =
+                    self.consume(scanner::TokenType::RightBracket, "Expect ']' after index")?;
+                let span = expr.span.to(bracket.span());
+                expr = expr::Expr::new(
+                    expr::ExprKind::Index(Box::new(expr), bracket, Box::new(index)),
+                    span,
+                );
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
 
-            return Ok(expr::Expr::Unary(op, right));
+    fn finish_call(&mut self, callee: expr::Expr) -> Result<expr::Expr, LoxError> {
+        let mut arguments = Vec::new();
+        if !self.check(scanner::TokenType::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(LoxError::parse(
+                        self.peek(),
+                        String::from("Can't have more than 255 arguments"),
+                    ));
+                }
+                arguments.push(self.expression()?);
+                if !self.matches(scanner::TokenType::Comma) {
+                    break;
+                }
+            }
         }
 
-        self.primary()
+        let paren = self.consume(
+            scanner::TokenType::RightParen,
+            "Expect ')' after arguments.",
+        )?;
+
+        let span = callee.span.to(paren.span());
+        Ok(expr::Expr::new(
+            expr::ExprKind::Call(Box::new(callee), paren, arguments),
+            span,
+        ))
     }
 
-    fn factor(&mut self) -> Result<expr::Expr, io::Error> {
+    fn factor(&mut self) -> Result<expr::Expr, LoxError> {
         let mut expr = self.unary()?;
 
         while self.match_one_of(vec![
@@ -141,27 +294,29 @@ impl Parser {
         ]) {
             let op = self.previous().clone();
             let right = Box::new(self.unary()?);
+            let span = expr.span.to(right.span);
             let left = Box::new(expr);
-            expr = expr::Expr::Binary(left, op, right);
+            expr = expr::Expr::new(expr::ExprKind::Binary(left, op, right), span);
         }
 
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<expr::Expr, io::Error> {
+    fn term(&mut self) -> Result<expr::Expr, LoxError> {
         let mut expr = self.factor()?;
 
         while self.match_one_of(vec![scanner::TokenType::Minus, scanner::TokenType::Plus]) {
             let op = self.previous().clone();
             let right = Box::new(self.factor()?);
+            let span = expr.span.to(right.span);
             let left = Box::new(expr);
-            expr = expr::Expr::Binary(left, op, right);
+            expr = expr::Expr::new(expr::ExprKind::Binary(left, op, right), span);
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<expr::Expr, io::Error> {
+    fn comparison(&mut self) -> Result<expr::Expr, LoxError> {
         // let mut expr = self.addition()?;
         let mut expr = self.term()?;
 
@@ -175,13 +330,14 @@ impl Parser {
             // let right = Box::new(self.addition()?);
             let right = Box::new(self.term()?);
 
-            let left = Box::new(expr.clone());
-            expr = expr::Expr::Binary(left, operator_token, right);
+            let span = expr.span.to(right.span);
+            let left = Box::new(expr);
+            expr = expr::Expr::new(expr::ExprKind::Binary(left, operator_token, right), span);
         }
         Ok(expr)
     }
 
-    fn equality(&mut self) -> Result<expr::Expr, io::Error> {
+    fn equality(&mut self) -> Result<expr::Expr, LoxError> {
         let mut expr = self.comparison()?;
         while self.match_one_of(vec![
             scanner::TokenType::EqualEqual,
@@ -189,77 +345,120 @@ impl Parser {
         ]) {
             let op = self.previous().clone();
             let right = Box::new(self.comparison()?);
+            let span = expr.span.to(right.span);
             let left = Box::new(expr);
-            expr = expr::Expr::Binary(left, op, right);
+            expr = expr::Expr::new(expr::ExprKind::Binary(left, op, right), span);
         }
 
         Ok(expr)
     }
-    fn or(&mut self) -> Result<expr::Expr, io::Error> {
+    fn or(&mut self) -> Result<expr::Expr, LoxError> {
         let mut expr = self.and()?;
         while self.match_one_of(vec![scanner::TokenType::Or]) {
             let operator = self.previous();
             let right = self.and()?;
-            expr = expr::Expr::Logical(Box::new(expr), operator, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = expr::Expr::new(
+                expr::ExprKind::Logical(Box::new(expr), operator, Box::new(right)),
+                span,
+            );
         }
 
         Ok(expr)
     }
-    fn and(&mut self) -> Result<expr::Expr, io::Error> {
+    fn and(&mut self) -> Result<expr::Expr, LoxError> {
         let mut expr = self.equality()?;
 
         while self.match_one_of(vec![scanner::TokenType::And]) {
             let operator = self.previous();
             let right = self.equality()?;
-            expr = expr::Expr::Logical(Box::new(expr), operator, Box::new(right));
+            let span = expr.span.to(right.span);
+            expr = expr::Expr::new(
+                expr::ExprKind::Logical(Box::new(expr), operator, Box::new(right)),
+                span,
+            );
         }
 
         Ok(expr)
     }
 
-    fn assignment(&mut self) -> Result<expr::Expr, io::Error> {
+    fn assignment(&mut self) -> Result<expr::Expr, LoxError> {
         let expr = self.or()?;
         if self.match_one_of(vec![scanner::TokenType::Equal]) {
+            let equals = self.previous();
             let value = self.assignment()?;
+            let span = expr.span.to(value.span);
 
-            match expr {
-                expr::Expr::Identifier(tok) => return Ok(expr::Expr::Assign(tok, Box::new(value))),
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Failed to do assignment it is not identifier",
-                    ))
+            let kind = match expr.kind {
+                expr::ExprKind::Identifier(tok, _) => {
+                    expr::ExprKind::Assign(tok, Box::new(value), None)
                 }
-            }
+                expr::ExprKind::Get(object, name) => {
+                    expr::ExprKind::Set(object, name, Box::new(value))
+                }
+                expr::ExprKind::Index(object, bracket, index) => {
+                    expr::ExprKind::IndexSet(object, bracket, index, Box::new(value))
+                }
+                _ => return Err(LoxError::parse(
+                    &equals,
+                    String::from("Invalid assignment target"),
+                )
+                .with_help(
+                    "only variables, properties and list or map elements can be assigned to",
+                )),
+            };
+            Ok(expr::Expr::new(kind, span))
         } else {
-            return Ok(expr);
+            Ok(expr)
         }
     }
-    fn expression(&mut self) -> Result<expr::Expr, io::Error> {
+    fn expression(&mut self) -> Result<expr::Expr, LoxError> {
         self.assignment()
     }
-    fn expression_statement(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn expression_statement(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.peek().span();
         let expr = self.expression()?;
-        self.consume(scanner::TokenType::SemiColon, "Expected ; after value")?;
-        Ok(expr::Stmt::Expr(expr))
+        if !(self.repl && self.is_at_end()) {
+            self.consume(scanner::TokenType::SemiColon, "Expected ; after value")?;
+        }
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Expr(expr),
+            self.span_from(start),
+        ))
     }
-    fn print_statement(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn print_statement(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
         let expr = self.expression()?;
         self.consume(scanner::TokenType::SemiColon, "Expected ; after value")?;
-        Ok(expr::Stmt::Print(expr))
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Print(expr),
+            self.span_from(start),
+        ))
     }
 
-    fn block_stmt(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn block(&mut self) -> Result<Vec<expr::Stmt>, LoxError> {
         let mut statements = Vec::new();
 
         while !self.check(scanner::TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.statement()?);
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
         }
 
         self.consume(scanner::TokenType::RightBrace, "Expected } after block")?;
-        Ok(expr::Stmt::Block(statements))
+        Ok(statements)
     }
-    fn if_stmt(&mut self) -> Result<expr::Stmt, io::Error> {
+
+    fn block_stmt(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
+        let statements = self.block()?;
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Block(statements),
+            self.span_from(start),
+        ))
+    }
+    fn if_stmt(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
         self.consume(scanner::TokenType::LeftParen, "Expected '(' after if")?;
         let condition = self.expression()?;
         self.consume(scanner::TokenType::RightParen, "exprected ')' after if")?;
@@ -271,20 +470,29 @@ impl Parser {
             else_branch = Some(Box::new(self.statement()?))
         }
 
-        Ok(expr::Stmt::If(condition, then_branch, else_branch))
+        Ok(expr::Stmt::new(
+            expr::StmtKind::If(condition, then_branch, else_branch),
+            self.span_from(start),
+        ))
     }
 
-    fn while_stmt(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn while_stmt(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
         self.consume(scanner::TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(scanner::TokenType::RightParen, "Expect ')' after 'while'.")?;
 
         let body = self.statement()?;
 
-        Ok(expr::Stmt::While(condition, Box::new(body)))
+        Ok(expr::Stmt::new(
+            expr::StmtKind::While(condition, Box::new(body), None, false),
+            self.span_from(start),
+        ))
     }
 
-    fn for_stmt(&mut self) -> Result<expr::Stmt, io::Error> {
+    // Desugared nodes all take the span of the whole `for` statement.
+    fn for_stmt(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
         self.consume(scanner::TokenType::LeftParen, "Expected ( after for.")?;
 
         let mut maybe_initializer: Option<expr::Stmt> = None;
@@ -319,28 +527,134 @@ impl Parser {
         )?;
 
         let mut body = self.statement()?;
-
-        if let Some(increment) = maybe_increment {
-            body = expr::Stmt::Block(vec![body, expr::Stmt::Expr(increment)])
-        }
+        let span = self.span_from(start);
 
         let condition = match maybe_condition {
             Some(cond) => cond,
-            None => expr::Expr::Literal(expr::Literal::True),
+            None => expr::Expr::new(expr::ExprKind::Literal(expr::Literal::True), span),
         };
-        body = expr::Stmt::While(condition, Box::new(body));
+        body = expr::Stmt::new(
+            expr::StmtKind::While(condition, Box::new(body), maybe_increment, true),
+            span,
+        );
 
         if let Some(initializer) = maybe_initializer {
-            body = expr::Stmt::Block(vec![initializer, body])
+            body = expr::Stmt::new(expr::StmtKind::Block(vec![initializer, body]), span)
         }
         let body = body;
 
         Ok(body)
     }
 
-    fn statement(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn function(&mut self, kind: &str) -> Result<expr::Function, LoxError> {
+        let name = self.consume(
+            scanner::TokenType::Identifier,
+            &format!("Expect {} name", kind),
+        )?;
+        self.consume(
+            scanner::TokenType::LeftParen,
+            &format!("Expect '(' after {} name", kind),
+        )?;
+
+        let mut params = Vec::new();
+        if !self.check(scanner::TokenType::RightParen) {
+            loop {
+                if params.len() >= 255 {
+                    return Err(LoxError::parse(
+                        self.peek(),
+                        String::from("Can't have more than 255 parameters"),
+                    ));
+                }
+                params.push(self.consume(scanner::TokenType::Identifier, "Expect parameter name")?);
+                if !self.matches(scanner::TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            scanner::TokenType::RightParen,
+            "Expect ')' after parameters",
+        )?;
+
+        self.consume(
+            scanner::TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body", kind),
+        )?;
+        let body = self.block()?;
+
+        Ok(expr::Function {
+            name,
+            params,
+            body: Rc::from(body),
+        })
+    }
+
+    fn class_declaration(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
+        let name = self.consume(scanner::TokenType::Identifier, "Expect class name")?;
+
+        let mut superclass = None;
+        if self.matches(scanner::TokenType::Less) {
+            self.consume(scanner::TokenType::Identifier, "Expect superclass name")?;
+            superclass = Some(expr::Expr::new(
+                expr::ExprKind::Identifier(self.previous(), None),
+                self.previous().span(),
+            ));
+        }
+        self.consume(
+            scanner::TokenType::LeftBrace,
+            "Expect '{' before class body",
+        )?;
+
+        let mut methods = Vec::new();
+        while !self.check(scanner::TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(
+            scanner::TokenType::RightBrace,
+            "Expect '}' after class body",
+        )?;
+
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Class(name, superclass, methods),
+            self.span_from(start),
+        ))
+    }
+
+    fn return_stmt(&mut self) -> Result<expr::Stmt, LoxError> {
+        let keyword = self.previous();
+        let value = if !self.check(scanner::TokenType::SemiColon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            scanner::TokenType::SemiColon,
+            "Expect ';' after return value",
+        )?;
+        let span = self.span_from(keyword.span());
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Return(keyword, value),
+            span,
+        ))
+    }
+
+    fn statement(&mut self) -> Result<expr::Stmt, LoxError> {
         if self.match_one_of(vec![scanner::TokenType::Print]) {
             return self.print_statement();
+        } else if self.match_one_of(vec![scanner::TokenType::Fun]) {
+            let start = self.previous().span();
+            let function = self.function("function")?;
+            return Ok(expr::Stmt::new(
+                expr::StmtKind::Function(function),
+                self.span_from(start),
+            ));
+        } else if self.match_one_of(vec![scanner::TokenType::Class]) {
+            return self.class_declaration();
+        } else if self.match_one_of(vec![scanner::TokenType::Return]) {
+            return self.return_stmt();
         } else if self.match_one_of(vec![scanner::TokenType::Var]) {
             return self.var_declaration();
         } else if self.match_one_of(vec![scanner::TokenType::LeftBrace]) {
@@ -351,11 +665,22 @@ impl Parser {
             return self.while_stmt();
         } else if self.match_one_of(vec![scanner::TokenType::For]) {
             return self.for_stmt();
+        } else if self.match_one_of(vec![scanner::TokenType::Break]) {
+            let keyword = self.previous();
+            self.consume(scanner::TokenType::SemiColon, "Expect ';' after 'break'")?;
+            let span = self.span_from(keyword.span());
+            return Ok(expr::Stmt::new(expr::StmtKind::Break(keyword), span));
+        } else if self.match_one_of(vec![scanner::TokenType::Continue]) {
+            let keyword = self.previous();
+            self.consume(scanner::TokenType::SemiColon, "Expect ';' after 'continue'")?;
+            let span = self.span_from(keyword.span());
+            return Ok(expr::Stmt::new(expr::StmtKind::Continue(keyword), span));
         }
 
         self.expression_statement()
     }
-    fn var_declaration(&mut self) -> Result<expr::Stmt, io::Error> {
+    fn var_declaration(&mut self) -> Result<expr::Stmt, LoxError> {
+        let start = self.previous().span();
         let name = self.consume(
             scanner::TokenType::Identifier,
             "Expected Identifier in var decl",
@@ -363,23 +688,67 @@ impl Parser {
         let initializer = match self.match_one_of(vec![scanner::TokenType::Equal]) {
             true => self.expression()?,
             false => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!("Var decl requires it to match to equals. Got {name:?}"),
-                ))
+                return Err(LoxError::parse(
+                    self.peek(),
+                    String::from("Var decl requires an initializer"),
+                )
+                .with_help("write `= nil` to start the variable out empty"))
             }
         };
 
         self.consume(scanner::TokenType::SemiColon, "Expected ; after var decl")?;
 
-        Ok(expr::Stmt::Var(name.lexme, initializer))
+        Ok(expr::Stmt::new(
+            expr::StmtKind::Var(name, initializer),
+            self.span_from(start),
+        ))
+    }
+
+    // Parses a statement, recording any syntax error and skipping ahead to
+    // the next statement so that later errors are still found.
+    fn declaration(&mut self) -> Option<expr::Stmt> {
+        match self.statement() {
+            Ok(stmt) => Some(stmt),
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.previous().tok_type == scanner::TokenType::SemiColon {
+                return;
+            }
+            match self.peek().tok_type {
+                scanner::TokenType::Class
+                | scanner::TokenType::Fun
+                | scanner::TokenType::Var
+                | scanner::TokenType::For
+                | scanner::TokenType::If
+                | scanner::TokenType::While
+                | scanner::TokenType::Print
+                | scanner::TokenType::Return => return,
+                _ => self.advance(),
+            };
+        }
     }
 
-    pub fn parse(&mut self) -> Result<Vec<expr::Stmt>, io::Error> {
+    // Returns every syntax error in the program, in source order.
+    pub fn parse(&mut self) -> Result<Vec<expr::Stmt>, Vec<LoxError>> {
         let mut stmts = Vec::<expr::Stmt>::new();
         while !self.is_at_end() {
-            stmts.push(self.statement()?);
+            if let Some(stmt) = self.declaration() {
+                stmts.push(stmt);
+            }
+        }
+        if self.errors.is_empty() {
+            Ok(stmts)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        Ok(stmts)
     }
 }
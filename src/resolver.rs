@@ -0,0 +1,313 @@
+use crate::error::LoxError;
+use crate::expr;
+use crate::scanner;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Copy, Clone, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// Walks the AST once before it runs, filling in how many scopes away each
+// local variable was declared and rejecting code that can never be valid.
+// Errors are collected and the walk carries on, so they're all reported
+// together before anything runs.
+pub struct Resolver {
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    loop_depth: usize,
+    errors: Vec<LoxError>,
+}
+
+impl Resolver {
+    pub fn new() -> Resolver {
+        Resolver {
+            scopes: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            loop_depth: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, stmts: &mut [expr::Stmt]) -> Result<(), Vec<LoxError>> {
+        self.resolve_stmts(stmts);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_stmts(&mut self, stmts: &mut [expr::Stmt]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn error(&mut self, token: &scanner::Token, message: String) {
+        self.errors.push(LoxError::resolve(token, message));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &scanner::Token) {
+        let declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.lexme.clone(), false).is_some(),
+            None => false,
+        };
+        if declared {
+            self.error(
+                name,
+                String::from("Already a variable with this name in this scope"),
+            );
+        }
+    }
+
+    fn define(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        for (distance, scope) in self.scopes.iter().rev().enumerate() {
+            if scope.contains_key(name) {
+                return Some(distance);
+            }
+        }
+        None
+    }
+
+    fn resolve_function(&mut self, function: &mut expr::Function, kind: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = kind;
+        // Loops outside the function body can't be broken out of from inside it.
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth = 0;
+
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(&param.lexme);
+        }
+        // Nothing shares the body until it runs, so this never copies it.
+        self.resolve_stmts(Rc::make_mut(&mut function.body));
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
+    }
+
+    fn resolve_class(
+        &mut self,
+        name: &scanner::Token,
+        superclass: &mut Option<expr::Expr>,
+        methods: &mut [expr::Function],
+    ) {
+        self.declare(name);
+        self.define(&name.lexme);
+
+        if let Some(expr::Expr {
+            kind: expr::ExprKind::Identifier(super_name, _),
+            ..
+        }) = superclass
+        {
+            if super_name.lexme == name.lexme {
+                self.error(
+                    super_name,
+                    String::from("A class can't inherit from itself"),
+                );
+            }
+        }
+
+        if let Some(superclass) = superclass {
+            self.current_class = ClassType::Subclass;
+            self.resolve_expr(superclass);
+
+            self.begin_scope();
+            self.define("super");
+        }
+
+        self.begin_scope();
+        self.define("this");
+
+        for method in methods {
+            let kind = if method.name.lexme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, kind);
+        }
+
+        self.end_scope();
+        if superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &mut expr::Stmt) {
+        match &mut stmt.kind {
+            expr::StmtKind::Print(expr) | expr::StmtKind::Expr(expr) => self.resolve_expr(expr),
+            expr::StmtKind::Var(name, initializer) => {
+                self.declare(name);
+                self.resolve_expr(initializer);
+                self.define(&name.lexme);
+            }
+            expr::StmtKind::Block(statements) => {
+                self.begin_scope();
+                self.resolve_stmts(statements);
+                self.end_scope();
+            }
+            expr::StmtKind::If(condition, then_branch, else_branch) => {
+                self.resolve_expr(condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            expr::StmtKind::While(condition, body, increment, _) => {
+                self.resolve_expr(condition);
+                self.loop_depth += 1;
+                self.resolve_stmt(body);
+                self.loop_depth -= 1;
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+            }
+            expr::StmtKind::Break(keyword) | expr::StmtKind::Continue(keyword) => {
+                if self.loop_depth == 0 {
+                    self.error(
+                        keyword,
+                        format!("Can't use '{}' outside of a loop", keyword.lexme),
+                    );
+                }
+            }
+            expr::StmtKind::Function(function) => {
+                self.declare(&function.name);
+                self.define(&function.name.lexme);
+                self.resolve_function(function, FunctionType::Function)
+            }
+            expr::StmtKind::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, String::from("Can't return from top-level code"));
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(
+                            keyword,
+                            String::from("Can't return a value from an initializer"),
+                        );
+                    }
+                    self.resolve_expr(value);
+                }
+            }
+            expr::StmtKind::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+                self.resolve_class(name, superclass, methods);
+                self.current_class = enclosing_class;
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &mut expr::Expr) {
+        match &mut expr.kind {
+            expr::ExprKind::Literal(_) => (),
+            expr::ExprKind::Grouping(e) | expr::ExprKind::Unary(_, e) => self.resolve_expr(e),
+            expr::ExprKind::Binary(left, _, right) | expr::ExprKind::Logical(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right)
+            }
+            expr::ExprKind::Identifier(name, depth) => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(&name.lexme) == Some(&false) {
+                        self.error(
+                            name,
+                            String::from("Can't read local variable in its own initializer"),
+                        );
+                    }
+                }
+                *depth = self.resolve_local(&name.lexme);
+            }
+            expr::ExprKind::Assign(name, value, depth) => {
+                self.resolve_expr(value);
+                *depth = self.resolve_local(&name.lexme);
+            }
+            expr::ExprKind::Call(callee, _, args) => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            expr::ExprKind::Get(object, _) => self.resolve_expr(object),
+            expr::ExprKind::List(elements) => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            expr::ExprKind::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            expr::ExprKind::Index(object, _, index) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index)
+            }
+            expr::ExprKind::IndexSet(object, _, index, value) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value)
+            }
+            expr::ExprKind::Set(object, _, value) => {
+                self.resolve_expr(value);
+                self.resolve_expr(object)
+            }
+            expr::ExprKind::This(keyword, depth) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, String::from("Can't use 'this' outside of a class"));
+                }
+                *depth = self.resolve_local(&keyword.lexme);
+            }
+            expr::ExprKind::Super(keyword, _, depth) => {
+                match self.current_class {
+                    ClassType::None => {
+                        self.error(
+                            keyword,
+                            String::from("Can't use 'super' outside of a class"),
+                        );
+                    }
+                    ClassType::Class => {
+                        self.error(
+                            keyword,
+                            String::from("Can't use 'super' in a class with no superclass"),
+                        );
+                    }
+                    ClassType::Subclass => (),
+                }
+                *depth = self.resolve_local(&keyword.lexme);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
+use crate::error::LoxError;
 use crate::expr::Literal;
 use std::collections::HashMap;
 use std::fmt;
-use std::io::{Error, ErrorKind};
 #[derive(Copy, Clone, PartialEq)]
 pub enum TokenType {
     // Single-character tokens.
@@ -9,7 +9,10 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -35,7 +38,9 @@ pub enum TokenType {
 
     // Keywords.
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -51,6 +56,10 @@ pub enum TokenType {
     Var,
     While,
 
+    // Never written by hand: converts the value of a `${...}` in a string
+    // to a string.
+    Interpolate,
+
     Eof,
 }
 impl fmt::Debug for TokenType {
@@ -60,7 +69,10 @@ impl fmt::Debug for TokenType {
             TokenType::RightParen => write!(f, "RightParen"),
             TokenType::LeftBrace => write!(f, "LeftBrace"),
             TokenType::RightBrace => write!(f, "RightBrace"),
+            TokenType::LeftBracket => write!(f, "LeftBracket"),
+            TokenType::RightBracket => write!(f, "RightBracket"),
             TokenType::Comma => write!(f, "Comma"),
+            TokenType::Colon => write!(f, "Colon"),
             TokenType::Dot => write!(f, "Dot"),
             TokenType::Minus => write!(f, "Minus"),
             TokenType::Plus => write!(f, "Plus"),
@@ -83,7 +95,9 @@ impl fmt::Debug for TokenType {
             TokenType::Number => write!(f, "Number"),
 
             TokenType::And => write!(f, "And"),
+            TokenType::Break => write!(f, "Break"),
             TokenType::Class => write!(f, "Class"),
+            TokenType::Continue => write!(f, "Continue"),
             TokenType::Else => write!(f, "Else"),
             TokenType::False => write!(f, "False"),
             TokenType::Fun => write!(f, "Fun"),
@@ -99,59 +113,119 @@ impl fmt::Debug for TokenType {
             TokenType::Var => write!(f, "Var"),
             TokenType::While => write!(f, "While"),
 
+            TokenType::Interpolate => write!(f, "Interpolate"),
+
             TokenType::Eof => write!(f, "Eof"),
         }
     }
 }
+// A range of source code. `source` says which of the sources run so far it
+// is in, `start` and `end` are byte offsets into that source, and `line` and
+// `column` (both 1-based) locate `start`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub source: usize,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(source: usize, start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            source,
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    // The smallest span covering both `self` and a later span.
+    pub fn to(&self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..*self
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Token {
     pub tok_type: TokenType,
     pub lexme: String,
     pub literal: Literal,
+    pub source: usize,
     pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl fmt::Debug for Token {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         writeln!(
             fmt,
-            "\n\ttype: {:?}\n\tlexme: {}\n\tliteral: {:?}\n\tline: {}",
-            self.tok_type, self.lexme, self.literal, self.line
+            "\n\ttype: {:?}\n\tlexme: {}\n\tliteral: {:?}\n\tline: {}\n\tcolumn: {}\n\tspan: {}..{}",
+            self.tok_type, self.lexme, self.literal, self.line, self.column, self.start, self.end
         )
     }
 }
 
 impl Token {
-    pub fn new(tok_type: TokenType, lexme: String, literal: Literal, line: usize) -> Token {
+    pub fn new(tok_type: TokenType, lexme: String, literal: Literal, span: Span) -> Token {
         Token {
             tok_type,
             lexme,
             literal,
-            line,
+            source: span.source,
+            line: span.line,
+            column: span.column,
+            start: span.start,
+            end: span.end,
         }
     }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.source, self.start, self.end, self.line, self.column)
+    }
 }
 
+// `start` and `current` are byte offsets into `source`.
 pub struct Scanner {
     pub source: String,
+    // The id spans are given, so errors can find this source again.
+    source_id: usize,
     current: usize,
     start: usize,
     line: usize,
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
     pub tokens: Vec<Token>,
+    errors: Vec<LoxError>,
     keywords: HashMap<String, TokenType>,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Self {
         Scanner {
-            source: source,
+            source,
+            source_id: 0,
             current: 0,
             start: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
             tokens: Vec::new(),
+            errors: Vec::new(),
             keywords: vec![
                 ("and", TokenType::And),
+                ("break", TokenType::Break),
                 ("class", TokenType::Class),
+                ("continue", TokenType::Continue),
                 ("else", TokenType::Else),
                 ("false", TokenType::False),
                 ("for", TokenType::For),
@@ -173,23 +247,64 @@ impl Scanner {
         }
     }
 
+    pub fn set_source_id(&mut self, source_id: usize) {
+        self.source_id = source_id;
+    }
+
     fn advance(&mut self) -> char {
-        let c = self
-            .source
+        let c = self.source[self.current..]
             .chars()
-            .nth(self.current)
+            .next()
             .expect("Index does not exist for source");
-        self.current += 1;
+        self.current += c.len_utf8();
         c
     }
 
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
+    fn column(&self, offset: usize) -> usize {
+        self.source[self.line_start..offset].chars().count() + 1
+    }
+
+    fn span(&self) -> Span {
+        Span::new(
+            self.source_id,
+            self.start,
+            self.current,
+            self.start_line,
+            self.start_column,
+        )
+    }
+
+    // The span from `start` to the current position, on the current line.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(
+            self.source_id,
+            start,
+            self.current,
+            self.line,
+            self.column(start),
+        )
+    }
+
+    // Marks the current position as the start of the next token.
+    fn begin_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column(self.current);
+    }
+
     fn add_token(&mut self, tok_type: TokenType) {
         self.add_token_lit(tok_type, Literal::Nil);
     }
 
     fn add_token_lit(&mut self, tok_type: TokenType, lit: Literal) {
         let text = String::from(&self.source[self.start..self.current]);
-        self.tokens.push(Token::new(tok_type, text, lit, self.line));
+        self.tokens
+            .push(Token::new(tok_type, text, lit, self.span()));
     }
 
     fn match_next(&mut self, expected: char) -> bool {
@@ -197,22 +312,25 @@ impl Scanner {
             return false;
         }
 
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.current += expected.len_utf8();
 
         true
     }
-    fn scan_token(&mut self) -> Result<(), Error> {
+    fn scan_token(&mut self) -> Result<(), LoxError> {
         let c = self.advance();
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
+            ':' => self.add_token(TokenType::Colon),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
@@ -263,19 +381,21 @@ impl Scanner {
             ' ' => (),
             '\r' => (),
             '\t' => (),
-            '\n' => self.line += 1,
+            '\n' => self.newline(),
 
             '"' => self.string()?,
 
             // Operator
             _ => {
                 if self.is_digit(c) {
-                    self.number();
+                    self.number()?;
                 } else if self.is_alpha(c) {
                     self.identifier();
                 } else {
-                    let err_msg = "Unimplemented token";
-                    return Err(Error::new(ErrorKind::Other, err_msg));
+                    return Err(LoxError::scan(
+                        self.span(),
+                        format!("Unexpected character '{}'", c),
+                    ));
                 }
             }
         }
@@ -288,11 +408,10 @@ impl Scanner {
         }
 
         let text: String = String::from(&self.source[self.start..self.current]);
-        let tok_type: TokenType;
-        match self.keywords.get(text.as_str()) {
-            Some(t_type) => tok_type = *t_type,
-            None => tok_type = TokenType::Identifier,
-        }
+        let tok_type = match self.keywords.get(text.as_str()) {
+            Some(t_type) => *t_type,
+            None => TokenType::Identifier,
+        };
         self.add_token(tok_type);
     }
 
@@ -301,10 +420,10 @@ impl Scanner {
     }
 
     fn is_alpha(&self, c: char) -> bool {
-        (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+        c.is_ascii_lowercase() || c.is_ascii_uppercase() || c == '_'
     }
 
-    fn number(&mut self) {
+    fn number(&mut self) -> Result<(), LoxError> {
         while self.is_digit(self.peek()) {
             self.advance();
         }
@@ -315,6 +434,22 @@ impl Scanner {
                 self.advance();
             }
         }
+
+        // Swallow the rest of something like `123abc` so it's reported once.
+        if self.is_alpha(self.peek()) {
+            while self.is_alpha_numeric(self.peek()) {
+                self.advance();
+            }
+            return Err(LoxError::scan(
+                self.span(),
+                format!(
+                    "Malformed number '{}'",
+                    &self.source[self.start..self.current]
+                ),
+            )
+            .with_help("names can't start with a digit"));
+        }
+
         self.add_token_lit(
             TokenType::Number,
             Literal::Number(
@@ -323,62 +458,345 @@ impl Scanner {
                     .expect("Not a number"),
             ),
         );
+        Ok(())
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.chars().count() {
-            return '\0';
-        }
-
-        self.source.chars().nth(self.current + 1).unwrap()
+        let mut chars = self.source[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn is_digit(&self, c: char) -> bool {
-        c >= '0' && c <= '9'
+        c.is_ascii_digit()
+    }
+
+    // Adds a token that has no text of its own in the source, such as the
+    // `+` and parens an interpolated string is lowered into.
+    // They get an empty span at the current position.
+    fn add_synthetic_token(&mut self, tok_type: TokenType, lexme: &str) {
+        let span = Span::new(
+            self.source_id,
+            self.current,
+            self.current,
+            self.line,
+            self.column(self.current),
+        );
+        self.tokens.push(Token::new(
+            tok_type,
+            String::from(lexme),
+            Literal::Nil,
+            span,
+        ));
     }
 
-    fn string(&mut self) -> Result<(), Error> {
+    // Scans a string literal. `"a ${b} c"` is lowered into the tokens of
+    // `"a " + (b) + " c"`, so the parser never sees interpolation.
+    fn string(&mut self) -> Result<(), LoxError> {
+        // Where a `(` goes if the string turns out to be interpolated.
+        let first = self.tokens.len();
+        let opening = Span::new(
+            self.source_id,
+            self.start,
+            self.start,
+            self.start_line,
+            self.start_column,
+        );
+        let mut interpolated = false;
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            let c = self.advance();
+            match c {
+                '\n' => {
+                    self.newline();
+                    value.push(c);
+                }
+                // A backslash at the very end escapes nothing; the loop ends
+                // and the unterminated string is reported once, below.
+                '\\' if self.is_at_end() => (),
+                // A bad escape is reported but the rest of the string still
+                // scans as a string.
+                '\\' => match self.escape() {
+                    Ok(c) => value.push(c),
+                    Err(err) => self.errors.push(err),
+                },
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.add_token_lit(TokenType::String, Literal::String(value));
+                    value = String::new();
+                    self.interpolation()?;
+                    self.begin_token();
+                    interpolated = true;
+                }
+                _ => value.push(c),
             }
-            self.advance();
         }
         if self.is_at_end() {
-            let err_msg = "Unterminated string";
-            return Err(Error::new(ErrorKind::Other, err_msg));
+            return Err(
+                LoxError::scan(self.span(), String::from("Unterminated string"))
+                    .with_help("add a closing '\"'")
+                    .unterminated(),
+            );
         }
         self.advance();
 
-        let value: Literal =
-            Literal::String(String::from(&self.source[self.start + 1..self.current - 1]));
-        self.add_token_lit(TokenType::String, value);
+        self.add_token_lit(TokenType::String, Literal::String(value));
+        // An interpolated string is lowered to `("a" + str (e) + "b")`. The
+        // outer parens stop neighbouring operators binding into its pieces.
+        if interpolated {
+            let paren = Token::new(
+                TokenType::LeftParen,
+                String::from("("),
+                Literal::Nil,
+                opening,
+            );
+            self.tokens.insert(first, paren);
+            self.add_synthetic_token(TokenType::RightParen, ")");
+        }
         Ok(())
     }
 
-    fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
+    fn escape(&mut self) -> Result<char, LoxError> {
+        let start = self.current - 1;
+        match self.advance() {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'u' => self.unicode_escape(start),
+            c => Err(LoxError::scan(
+                self.span_from(start),
+                format!("Invalid escape sequence '\\{}'", c),
+            )),
         }
+    }
 
-        self.source.chars().nth(self.current).unwrap()
+    // Parses the `{XXXX}` part of a `\u{XXXX}` escape.
+    fn unicode_escape(&mut self, start: usize) -> Result<char, LoxError> {
+        if !self.match_next('{') {
+            return Err(LoxError::scan(
+                self.span_from(start),
+                String::from("Expected '{' after '\\u'"),
+            ));
+        }
+        let mut digits = String::new();
+        while self.peek().is_ascii_hexdigit() && digits.len() < 6 {
+            digits.push(self.advance());
+        }
+        if digits.is_empty() || !self.match_next('}') {
+            return Err(LoxError::scan(
+                self.span_from(start),
+                format!("Invalid unicode escape '\\u{{{}'", digits),
+            ));
+        }
+        match u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+        {
+            Some(c) => Ok(c),
+            None => Err(LoxError::scan(
+                self.span_from(start),
+                format!("Invalid unicode code point '\\u{{{}}}'", digits),
+            )),
+        }
     }
-    pub fn scan_tokens(&mut self) -> Result<(), Error> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token()?;
+
+    // Scans the tokens of a `${...}` expression up to its closing brace.
+    fn interpolation(&mut self) -> Result<(), LoxError> {
+        self.add_synthetic_token(TokenType::Plus, "+");
+        self.add_synthetic_token(TokenType::Interpolate, "str");
+        self.add_synthetic_token(TokenType::LeftParen, "(");
+
+        let mut depth = 0;
+        loop {
+            if self.is_at_end() {
+                return Err(LoxError::scan(
+                    self.span(),
+                    String::from("Unterminated interpolation in string"),
+                )
+                .unterminated());
+            }
+            if self.peek() == '}' && depth == 0 {
+                self.advance();
+                break;
+            }
+
+            self.begin_token();
+            let scanned = self.tokens.len();
+            if let Err(err) = self.scan_token() {
+                self.errors.push(err);
+            }
+            if self.tokens.len() > scanned {
+                match self.tokens[self.tokens.len() - 1].tok_type {
+                    TokenType::LeftBrace => depth += 1,
+                    TokenType::RightBrace => depth -= 1,
+                    _ => (),
+                }
+            }
         }
-        self.tokens.push(Token::new(
-            TokenType::Eof,
-            String::from(""),
-            Literal::Nil,
-            self.line,
-        ));
 
+        self.add_synthetic_token(TokenType::RightParen, ")");
+        self.add_synthetic_token(TokenType::Plus, "+");
         Ok(())
     }
 
+    fn peek(&self) -> char {
+        self.source[self.current..].chars().next().unwrap_or('\0')
+    }
+    // Scans the whole source, carrying on past lexical errors so that all
+    // of them are returned together.
+    pub fn scan_tokens(&mut self) -> Result<(), Vec<LoxError>> {
+        while !self.is_at_end() {
+            self.begin_token();
+            if let Err(err) = self.scan_token() {
+                self.errors.push(err);
+            }
+        }
+        // Anchor the end of input just past the last token rather than on
+        // whatever trailing whitespace follows it.
+        self.current = self.tokens.last().map_or(0, |token| token.end);
+        let before = &self.source[..self.current];
+        self.line = before.matches('\n').count() + 1;
+        self.line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        self.begin_token();
+        self.add_token(TokenType::Eof);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.chars().count()
+        self.current >= self.source.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scan(source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::new(String::from(source));
+        scanner.scan_tokens().unwrap();
+        scanner.tokens
+    }
+
+    fn errors(source: &str) -> Vec<LoxError> {
+        let mut scanner = Scanner::new(String::from(source));
+        scanner.scan_tokens().unwrap_err()
+    }
+
+    // Each token as its type, with the value of any string.
+    fn describe(source: &str) -> Vec<String> {
+        scan(source)
+            .iter()
+            .map(|token| match &token.literal {
+                Literal::String(value) => format!("String {:?}", value),
+                _ => format!("{:?}", token.tok_type),
+            })
+            .collect()
+    }
+
+    fn string_value(source: &str) -> String {
+        match &scan(source)[0].literal {
+            Literal::String(value) => value.clone(),
+            _ => panic!("{} didn't scan as a string", source),
+        }
+    }
+
+    #[test]
+    fn escapes_are_replaced() {
+        let value = string_value(r#""a\tb\nc\\d\"e\$f\0""#);
+        assert_eq!(value, "a\tb\nc\\d\"e$f\0");
+    }
+
+    #[test]
+    fn unicode_escape_is_replaced() {
+        assert_eq!(string_value(r#""\u{2603} \u{41}""#), "\u{2603} A");
+    }
+
+    #[test]
+    fn bad_escapes_are_all_reported() {
+        let errors = errors(r#""\q \u{110000} \u41""#);
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Invalid escape sequence '\\q'",
+                "Invalid unicode code point '\\u{110000}'",
+                "Expected '{' after '\\u'",
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolation_is_lowered_inside_parens() {
+        assert_eq!(
+            describe(r#""a${x}b" + "c""#),
+            [
+                "LeftParen",
+                "String \"a\"",
+                "Plus",
+                "Interpolate",
+                "LeftParen",
+                "Identifier",
+                "RightParen",
+                "Plus",
+                "String \"b\"",
+                "RightParen",
+                "Plus",
+                "String \"c\"",
+                "Eof",
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_interpolation_scans_inner_string() {
+        assert_eq!(
+            describe(r#""<${"(${x})"}>""#),
+            [
+                "LeftParen",
+                "String \"<\"",
+                "Plus",
+                "Interpolate",
+                "LeftParen",
+                "LeftParen",
+                "String \"(\"",
+                "Plus",
+                "Interpolate",
+                "LeftParen",
+                "Identifier",
+                "RightParen",
+                "Plus",
+                "String \")\"",
+                "RightParen",
+                "RightParen",
+                "Plus",
+                "String \">\"",
+                "RightParen",
+                "Eof",
+            ]
+        );
+    }
+
+    #[test]
+    fn braces_inside_interpolation_dont_end_it() {
+        let kinds = describe(r#""${ {"k": 1}["k"] }""#);
+        assert_eq!(kinds.iter().filter(|kind| *kind == "RightBrace").count(), 1);
+        assert_eq!(kinds[kinds.len() - 2], "RightParen");
+    }
+
+    #[test]
+    fn trailing_backslash_is_one_unterminated_string() {
+        let errors = errors("\"abc\\");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Unterminated string");
+        assert!(errors[0].unterminated);
     }
 }